@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::assembler::Token;
+
+// Everything that can go wrong while assembling a program. Each error carries the 1-based line
+// and column of the instruction or operand it was found in, so the assembler can report all of
+// them at once.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AssemblerError {
+    // The source could not be parsed from this point on. `found` is the rest of the offending line
+    // and `expected` describes what the grammar was looking for there.
+    ParseError { line: usize, column: usize, found: String, expected: &'static str },
+    UnknownOpcode { line: usize, column: usize, mnemonic: String },
+    BadOperandKind { line: usize, column: usize, operand: Token, expected: &'static str },
+    WrongOperandCount { line: usize, column: usize, found: usize, expected: usize },
+    UndefinedLabel { line: usize, column: usize, name: String },
+    DuplicateLabel { line: usize, column: usize, name: String },
+    // A label on `.data` or `.code`, which take up no space and so have no address of their own
+    LabelOnSection { line: usize, column: usize, name: String },
+    IntegerOutOfRange { line: usize, column: usize, value: i64 },
+    // A float literal given to an instruction, which has no room for one
+    FloatLiteralOperand { line: usize, column: usize, value: f64 },
+    // A register or float register number past the last one the VM has
+    UnknownRegister { line: usize, column: usize, operand: Token },
+    UnknownDirective { line: usize, column: usize, name: String },
+    // An instruction outside `.code` or a data directive outside `.data`
+    WrongSection { line: usize, column: usize },
+}
+
+impl AssemblerError {
+    pub fn line(&self) -> usize {
+        match self {
+            AssemblerError::ParseError { line, .. }
+            | AssemblerError::UnknownOpcode { line, .. }
+            | AssemblerError::BadOperandKind { line, .. }
+            | AssemblerError::WrongOperandCount { line, .. }
+            | AssemblerError::UndefinedLabel { line, .. }
+            | AssemblerError::DuplicateLabel { line, .. }
+            | AssemblerError::LabelOnSection { line, .. }
+            | AssemblerError::IntegerOutOfRange { line, .. }
+            | AssemblerError::FloatLiteralOperand { line, .. }
+            | AssemblerError::UnknownRegister { line, .. }
+            | AssemblerError::UnknownDirective { line, .. }
+            | AssemblerError::WrongSection { line, .. } => *line,
+        }
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            AssemblerError::ParseError { column, .. }
+            | AssemblerError::UnknownOpcode { column, .. }
+            | AssemblerError::BadOperandKind { column, .. }
+            | AssemblerError::WrongOperandCount { column, .. }
+            | AssemblerError::UndefinedLabel { column, .. }
+            | AssemblerError::DuplicateLabel { column, .. }
+            | AssemblerError::LabelOnSection { column, .. }
+            | AssemblerError::IntegerOutOfRange { column, .. }
+            | AssemblerError::FloatLiteralOperand { column, .. }
+            | AssemblerError::UnknownRegister { column, .. }
+            | AssemblerError::UnknownDirective { column, .. }
+            | AssemblerError::WrongSection { column, .. } => *column,
+        }
+    }
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line(), self.column())?;
+        match self {
+            AssemblerError::ParseError { found, expected, .. } if found.is_empty() => {
+                write!(f, "unexpected end of input, expected {}", expected)
+            }
+            AssemblerError::ParseError { found, expected, .. } => {
+                write!(f, "unexpected `{}`, expected {}", found, expected)
+            }
+            AssemblerError::UnknownOpcode { mnemonic, .. } => write!(f, "unknown opcode {}", mnemonic),
+            AssemblerError::BadOperandKind { operand, expected, .. } => {
+                write!(f, "operand `{}` is not allowed here, expected {}", operand, expected)
+            }
+            AssemblerError::WrongOperandCount { found, expected, .. } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AssemblerError::UndefinedLabel { name, .. } => write!(f, "undefined label @{}", name),
+            AssemblerError::DuplicateLabel { name, .. } => {
+                write!(f, "label {} is already defined", name)
+            }
+            AssemblerError::LabelOnSection { name, .. } => {
+                write!(f, "label {} must be on an instruction or data directive, not a section", name)
+            }
+            AssemblerError::IntegerOutOfRange { value, .. } => {
+                write!(f, "{} does not fit in the operand", value)
+            }
+            AssemblerError::FloatLiteralOperand { value, .. } => write!(
+                f,
+                "float literal {} cannot be an instruction operand, declare it with .double and read it with loadf",
+                Token::FloatOperand { value: *value }
+            ),
+            AssemblerError::UnknownRegister { operand, .. } => {
+                write!(f, "there is no register {}", operand)
+            }
+            AssemblerError::UnknownDirective { name, .. } => write!(f, "unknown directive .{}", name),
+            AssemblerError::WrongSection { .. } => {
+                write!(f, "instructions belong in the .code section and data in the .data section")
+            }
+        }
+    }
+}
+
+impl Error for AssemblerError {}
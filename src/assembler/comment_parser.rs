@@ -0,0 +1,55 @@
+use nom::types::CompleteStr;
+use nom::multispace;
+
+// A line comment starts with `;` or `#!` and runs to the end of the line
+named!(line_comment<CompleteStr, CompleteStr>,
+    recognize!(
+        do_parse!(
+            alt!(tag!(";") | tag!("#!")) >>
+            take_till!(|c| c == '\n') >>
+            ()
+        )
+    )
+);
+
+// A block comment is anything between `/*` and `*/`, newlines included
+named!(block_comment<CompleteStr, CompleteStr>,
+    recognize!(
+        do_parse!(
+            tag!("/*") >>
+            take_until_and_consume!("*/") >>
+            ()
+        )
+    )
+);
+
+// Skips any mix of whitespace and comments, and succeeds even when there is none. This is the
+// separator `ws_comments!` places between tokens.
+named!(pub space_or_comment<CompleteStr, CompleteStr>,
+    recognize!(
+        many0!(
+            alt!(multispace | line_comment | block_comment)
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_space_or_comment() {
+        let result = space_or_comment(CompleteStr(" ; note\n  #! more\n/* a\nb */ load"));
+        assert_eq!(result, Ok((CompleteStr("load"), CompleteStr(" ; note\n  #! more\n/* a\nb */ "))));
+        let result = space_or_comment(CompleteStr("load"));
+        assert_eq!(result, Ok((CompleteStr("load"), CompleteStr(""))));
+        let result = space_or_comment(CompleteStr("; to the end"));
+        assert_eq!(result, Ok((CompleteStr(""), CompleteStr("; to the end"))));
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let result = space_or_comment(CompleteStr("/* never closed"));
+        assert_eq!(result, Ok((CompleteStr("/* never closed"), CompleteStr(""))));
+    }
+}
@@ -1,9 +1,13 @@
-use crate::assembler::Token;
+use crate::assembler::{SymbolTable, SymbolType, Token};
+use crate::assembler::assembler_errors::AssemblerError;
+use crate::instruction::{Opcode, OperandKind, INSTRUCTION_LENGTH, REGISTER_COUNT};
+use std::convert::TryFrom;
 use crate::assembler::opcode_parser::*;
 use nom::types::CompleteStr;
-use crate::assembler::operand_parser::operand;
+use crate::assembler::operand_parser::located_operand;
+use nom::alpha1;
 use crate::assembler::label_parser::label_declaration;
-use crate::assembler::directive_parser::directive;
+use crate::assembler::directive_parser::{directive, located_directive};
 //use the assembler instruction struct to combine different parser from opcode parser
 #[derive(Debug, PartialEq)]
 pub struct AssemblerInstruction {
@@ -12,30 +16,62 @@ pub struct AssemblerInstruction {
     pub operand2: Option<Token>,
     pub operand3: Option<Token>,
     pub label: Option<Token>,
-    pub directive: Option<Token>
+    pub directive: Option<Token>,
+    // The opcode as it was written, so an unknown one can be named
+    pub mnemonic: String,
+    // Where the instruction starts in the source, filled in by the program parser
+    pub line: usize,
+    pub column: usize,
+    // Where each operand starts in the source, filled in by the program parser so errors about an
+    // operand can point at it
+    pub operand_positions: Vec<(usize, usize)>,
 }
 
+// An instruction along with how much of the source was left where each of its operands starts,
+// which the program parser turns into `operand_positions`
+pub type LocatedInstruction = (AssemblerInstruction, Vec<usize>);
 
-named!(instruction_combined<CompleteStr, AssemblerInstruction>,
+// Separates operands parsed by `located_operand` into the tokens and where each one starts
+pub fn split_located(located: [Option<(usize, Token)>; 3]) -> ([Option<Token>; 3], Vec<usize>) {
+    let starts = located.iter().flatten().map(|(start, _)| *start).collect();
+    let [o1, o2, o3] = located;
+    ([o1.map(|(_, t)| t), o2.map(|(_, t)| t), o3.map(|(_, t)| t)], starts)
+}
+
+
+named!(located_instruction<CompleteStr, LocatedInstruction>,
     do_parse!(
         l: opt!(label_declaration) >>
+        m: peek!(alpha1) >>
         o: opcode >>
-        o1: opt!(operand) >>
-        o2: opt!(operand) >>
-        o3: opt!(operand) >>
+        o1: opt!(located_operand) >>
+        o2: opt!(located_operand) >>
+        o3: opt!(located_operand) >>
         (
-            AssemblerInstruction{
-                opcode: Some(o),
-                label: l,
-                directive: None,
-                operand1: o1,
-                operand2: o2,
-                operand3: o3,
+            {
+                let ([operand1, operand2, operand3], operand_starts) = split_located([o1, o2, o3]);
+                let instruction = AssemblerInstruction{
+                    opcode: Some(o),
+                    label: l,
+                    directive: None,
+                    operand1,
+                    operand2,
+                    operand3,
+                    mnemonic: m.to_string(),
+                    line: 1,
+                    column: 1,
+                    operand_positions: vec![],
+                };
+                (instruction, operand_starts)
             }
         )
     )
 );
 
+named!(instruction_combined<CompleteStr, AssemblerInstruction>,
+    map!(located_instruction, |(instruction, _)| instruction)
+);
+
 // Will try to parse out any of the Instruction forms
 named!(pub instruction<CompleteStr, AssemblerInstruction>,
     do_parse!(
@@ -48,69 +84,407 @@ named!(pub instruction<CompleteStr, AssemblerInstruction>,
     )
 );
 
+// `li $r #value` loads any 32-bit constant. It becomes a LOAD of the low half, followed by a LUI
+// of the high half when LOAD's sign extension would not already produce it.
+named!(load_immediate<CompleteStr, Vec<LocatedInstruction>>,
+    do_parse!(
+        l: opt!(label_declaration) >>
+        verify!(alpha1, |m: CompleteStr| m.0 == "li") >>
+        o1: opt!(located_operand) >>
+        o2: opt!(located_operand) >>
+        o3: opt!(located_operand) >>
+        (
+            expand_load_immediate(l, [o1, o2, o3])
+        )
+    )
+);
+
+// Operands that are not a register and an integer are left on a single LUI, which takes the same
+// shape of operands as `li`, so they are reported the way any other instruction's would be
+fn expand_load_immediate(label: Option<Token>, located: [Option<(usize, Token)>; 3]) -> Vec<LocatedInstruction> {
+    let ([operand1, operand2, operand3], operand_starts) = split_located(located);
+    let instruction = |label, code, operand2| {
+        let instruction = AssemblerInstruction {
+            opcode: Some(Token::Op { code }),
+            label,
+            directive: None,
+            operand1: operand1.clone(),
+            operand2,
+            operand3: operand3.clone(),
+            mnemonic: "li".to_string(),
+            line: 1,
+            column: 1,
+            operand_positions: vec![],
+        };
+        (instruction, operand_starts.clone())
+    };
+    let (reg_num, value) = match (&operand1, &operand2, &operand3) {
+        (Some(Token::Register { reg_num }), Some(Token::IntegerOperand { value }), None) => (*reg_num, *value),
+        _ => return vec![instruction(label, Opcode::LUI, operand2.clone())],
+    };
+    let low = i32::from(value as i16);
+    let high = i32::from((value >> 16) as u16);
+    let mut expanded = vec![instruction(label, Opcode::LOAD, Some(Token::IntegerOperand { value: low }))];
+    // The LOAD already reports an unknown register, which the LUI would only repeat
+    if low != value && (reg_num as usize) < REGISTER_COUNT {
+        expanded.push(instruction(None, Opcode::LUI, Some(Token::IntegerOperand { value: high })));
+    }
+    expanded
+}
+
+// One line of source along with where its operands start. A pseudo-instruction may turn into
+// several instructions.
+named!(pub located_statement<CompleteStr, Vec<LocatedInstruction>>,
+    alt!(
+        load_immediate |
+        map!(located_instruction, |i| vec![i]) |
+        map!(located_directive, |d| vec![d])
+    )
+);
+
+// One line of source, which a pseudo-instruction may turn into several instructions
+named!(pub statement<CompleteStr, Vec<AssemblerInstruction>>,
+    map!(located_statement, |located| located.into_iter().map(|(i, _)| i).collect())
+);
+
 impl AssemblerInstruction {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    // `address` is where this instruction will sit in the final bytecode, so that a label can be
+    // turned into an offset relative to it
+    pub fn to_bytes(&self, symbols: &SymbolTable, address: u32) -> Result<Vec<u8>, Vec<AssemblerError>> {
         let mut results:Vec<u8> = vec![];
+        let mut errors = vec![];
         if let Some(ref token) = self.opcode{
-            match token {
-                Token::Op { code } => match code {
-                    _ => {
-                        let b: u8 = (*code).into();
-                        results.push(b);
-
-                    }
+            let code = match token {
+                Token::Op { code: Opcode::IGL } => {
+                    return Err(vec![AssemblerError::UnknownOpcode {
+                        line: self.line,
+                        column: self.column,
+                        mnemonic: self.mnemonic.clone(),
+                    }]);
                 },
+                Token::Op { code } => self.resolve_opcode(*code),
                 _ => {
-                    println!("Non-opcode found in opcode field");
-                    std::process::exit(1);
+                    return Err(vec![AssemblerError::UnknownOpcode {
+                        line: self.line,
+                        column: self.column,
+                        mnemonic: self.mnemonic.clone(),
+                    }]);
                 }
             };
-    
-            for operand in &[&self.operand1, &self.operand2, &self.operand3] {
-                if let Some(token) = operand {
-                    AssemblerInstruction::extract_operand(token, &mut results)
+            results.push(code.into());
+
+            let operands: Vec<&Token> = self.operands().collect();
+            // A float can only be read from the heap, so say that instead of complaining about the
+            // operand count or kind
+            for (index, operand) in operands.iter().enumerate() {
+                if let Token::FloatOperand { value } = operand {
+                    let (line, column) = self.operand_position(index);
+                    return Err(vec![AssemblerError::FloatLiteralOperand { line, column, value: *value }]);
+                }
+            }
+            let signature = code.operands();
+            if operands.len() != signature.len() {
+                return Err(vec![AssemblerError::WrongOperandCount {
+                    line: self.line,
+                    column: self.column,
+                    found: operands.len(),
+                    expected: signature.len(),
+                }]);
+            }
+            for (index, (token, kind)) in operands.iter().zip(signature).enumerate() {
+                let extracted = match token {
+                    Token::LabelUsage { name } => {
+                        self.extract_label(*kind, name, index, symbols, address, &mut results)
+                    }
+                    _ => self.extract_operand(*kind, token, index, &mut results),
+                };
+                if let Err(e) = extracted {
+                    errors.push(e);
                 }
-             }
-             while results.len() < 4 {
+            }
+            while results.len() < 4 {
                 results.push(0);
             }
         }
 
-        return results;
+        if errors.is_empty() {
+            Ok(results)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn operands(&self) -> impl Iterator<Item = &Token> {
+        self.operand1.iter().chain(self.operand2.iter()).chain(self.operand3.iter())
+    }
+
+    // The line and column of the operand at `index`, or of the instruction when its operands'
+    // positions were not recorded
+    fn operand_position(&self, index: usize) -> (usize, usize) {
+        self.operand_positions.get(index).copied().unwrap_or((self.line, self.column))
+    }
+
+    // Jumps given a label or an integer instead of a register are assembled to their immediate
+    // variant
+    fn resolve_opcode(&self, code: Opcode) -> Opcode {
+        let has_immediate = self
+            .operands()
+            .any(|o| matches!(o, Token::LabelUsage { .. } | Token::IntegerOperand { .. }));
+        match code.with_label_target() {
+            Some(immediate) if has_immediate => immediate,
+            _ => code,
+        }
+    }
+
+    pub fn directive_name(&self) -> Option<&str> {
+        match &self.directive {
+            Some(Token::Directive { name }) => Some(name),
+            _ => None,
+        }
+    }
+
+    // How many bytes a data directive takes up, so labels can be laid out before anything is
+    // encoded. Malformed directives count as empty and are reported by `data_bytes`.
+    pub fn data_size(&self) -> usize {
+        let operands = self.operands();
+        match self.directive_name() {
+            Some("asciiz") => match &self.operand1 {
+                Some(Token::IrString { name }) => name.len() + 1,
+                _ => 0,
+            },
+            Some("byte") => operands.count(),
+            Some("word") => 4 * operands.count(),
+            Some("double") => 8 * operands.count(),
+            Some("space") => match &self.operand1 {
+                Some(Token::IntegerOperand { value }) if *value >= 0 => *value as usize,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    // Encodes a data directive: `.asciiz` a zero-terminated string, `.byte`, `.word` and `.double`
+    // one to three values, `.space` a number of zeroed bytes
+    pub fn data_bytes(&self) -> Result<Vec<u8>, AssemblerError> {
+        let mut results = vec![];
+        let name = self.directive_name().unwrap_or_default();
+        let expected = match name {
+            "asciiz" | "space" => 1,
+            "byte" | "word" | "double" => self.operands().count().max(1),
+            _ => {
+                return Err(AssemblerError::UnknownDirective {
+                    line: self.line,
+                    column: self.column,
+                    name: name.to_string(),
+                });
+            }
+        };
+        if self.operands().count() != expected {
+            return Err(AssemblerError::WrongOperandCount {
+                line: self.line,
+                column: self.column,
+                found: self.operands().count(),
+                expected,
+            });
+        }
+        for (index, token) in self.operands().enumerate() {
+            let (line, column) = self.operand_position(index);
+            match (name, token) {
+                ("asciiz", Token::IrString { name }) => {
+                    results.extend_from_slice(name.as_bytes());
+                    results.push(0);
+                },
+                ("byte", Token::IntegerOperand { value }) => {
+                    if *value < i32::from(i8::MIN) || *value > i32::from(u8::MAX) {
+                        return Err(AssemblerError::IntegerOutOfRange {
+                            line,
+                            column,
+                            value: i64::from(*value),
+                        });
+                    }
+                    results.push(*value as u8);
+                },
+                ("word", Token::IntegerOperand { value }) => {
+                    results.extend_from_slice(&value.to_be_bytes());
+                },
+                ("double", Token::FloatOperand { value }) => {
+                    results.extend_from_slice(&value.to_be_bytes());
+                },
+                ("double", Token::IntegerOperand { value }) => {
+                    results.extend_from_slice(&f64::from(*value).to_be_bytes());
+                },
+                ("space", Token::IntegerOperand { value }) => {
+                    if *value < 0 {
+                        return Err(AssemblerError::IntegerOutOfRange {
+                            line,
+                            column,
+                            value: i64::from(*value),
+                        });
+                    }
+                    results.resize(*value as usize, 0);
+                },
+                _ => {
+                    return Err(AssemblerError::BadOperandKind {
+                        line,
+                        column,
+                        operand: token.clone(),
+                        expected: match name {
+                            "asciiz" => "a string",
+                            "double" => "a number",
+                            _ => "an integer",
+                        },
+                    });
+                }
+            }
+        }
+        Ok(results)
     }
+
     pub fn is_label(&self) -> bool {
         self.label.is_some()
     }
 
     pub fn get_label_name(&self) -> Option<String> {
         match &self.label {
-            Some(l) => match l {
-                Token::LabelDeclaration { name } => Some(name.clone()),
-                _ => None,
-            },
-            None => None,
+            Some(Token::LabelDeclaration { name }) => Some(name.clone()),
+            _ => None,
         }
     }
 
-
-    //convert numbers to bytecode
-    fn extract_operand(t: &Token, results: &mut Vec<u8>) {
-        match t {
-            Token::Register { reg_num } => {
+    //convert registers and numbers to bytecode, laid out the way the opcode's signature says
+    fn extract_operand(&self, kind: OperandKind, t: &Token, index: usize, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        match (kind, t) {
+            (OperandKind::Register, Token::Register { reg_num })
+            | (OperandKind::FloatRegister, Token::FloatRegister { reg_num }) => {
+                if *reg_num as usize >= REGISTER_COUNT {
+                    return Err(AssemblerError::UnknownRegister { line, column, operand: t.clone() });
+                }
                 results.push(*reg_num);
+                Ok(())
             }
-            Token::IntegerOperand { value } => {
-                let converted = *value as u16;
-                let byte1 = converted;
-                let byte2 = converted >> 8;
-                results.push(byte2 as u8);
-                results.push(byte1 as u8);
+            (OperandKind::Imm8, Token::IntegerOperand { value }) => {
+                self.push_u8(i64::from(*value), index, results)
             }
-            _ => {
-                println!("Opcode found in operand field");
-                std::process::exit(1);
+            // The VM sign-extends these, so they take -32768 to 32767
+            (OperandKind::Imm16, Token::IntegerOperand { value })
+            | (OperandKind::RelativeOffset, Token::IntegerOperand { value }) => {
+                self.push_i16(i64::from(*value), index, results)
+            }
+            // LUI just takes 16 bits, so either a signed or an unsigned spelling of them
+            (OperandKind::Upper16, Token::IntegerOperand { value }) if *value < 0 => {
+                self.push_i16(i64::from(*value), index, results)
+            }
+            (OperandKind::BranchOffset, Token::IntegerOperand { value }) => {
+                self.push_i8(i64::from(*value), index, results)
+            }
+            (
+                OperandKind::Upper16
+                | OperandKind::Address
+                | OperandKind::ForwardOffset
+                | OperandKind::BackwardOffset,
+                Token::IntegerOperand { value },
+            ) => self.push_u16(i64::from(*value), index, results),
+            _ => Err(AssemblerError::BadOperandKind {
+                line,
+                column,
+                operand: t.clone(),
+                expected: kind.description(),
+            }),
+        }
+    }
+
+    //convert a label to an absolute address, or for relative jumps to the distance from the end
+    //of this instruction, which must point the way the jump goes
+    fn extract_label(&self, kind: OperandKind, name: &str, index: usize, symbols: &SymbolTable, address: u32, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        let bad_operand = |expected| AssemblerError::BadOperandKind {
+            line,
+            column,
+            operand: Token::LabelUsage { name: name.to_string() },
+            expected,
+        };
+        if let OperandKind::Register | OperandKind::FloatRegister | OperandKind::Imm8 | OperandKind::Upper16 = kind {
+            return Err(bad_operand(kind.description()));
+        }
+        let target = match symbols.symbol_value(name) {
+            Some(target) => i64::from(target),
+            None => {
+                return Err(AssemblerError::UndefinedLabel {
+                    line,
+                    column,
+                    name: name.to_string(),
+                });
             }
         };
+        // A data label is an offset into the heap, which only makes sense as a value to load
+        if kind != OperandKind::Imm16 && symbols.symbol_type(name) == Some(&SymbolType::Data) {
+            return Err(bad_operand("a code label"));
+        }
+        let end = i64::from(address) + INSTRUCTION_LENGTH as i64;
+        match kind {
+            OperandKind::ForwardOffset => self.push_u16(target - end, index, results),
+            OperandKind::BackwardOffset => self.push_u16(end - target, index, results),
+            OperandKind::RelativeOffset => self.push_i16(target - end, index, results),
+            OperandKind::BranchOffset => self.push_i8(target - end, index, results),
+            // Loads the address a label stands for, which LOAD sign-extends
+            OperandKind::Imm16 => self.push_i16(target, index, results),
+            _ => self.push_u16(target, index, results),
+        }
+    }
+
+    fn push_u8(&self, value: i64, index: usize, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        if value < 0 || value > i64::from(u8::MAX) {
+            return Err(AssemblerError::IntegerOutOfRange {
+                line,
+                column,
+                value,
+            });
+        }
+        results.push(value as u8);
+        Ok(())
+    }
+
+    // Pushes a signed byte, such as a fused branch offset
+    fn push_i8(&self, value: i64, index: usize, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        if i8::try_from(value).is_err() {
+            return Err(AssemblerError::IntegerOutOfRange {
+                line,
+                column,
+                value,
+            });
+        }
+        results.push(value as u8);
+        Ok(())
+    }
+
+    // Pushes a signed 16-bit value in two's complement, such as LOAD's sign-extended immediate
+    fn push_i16(&self, value: i64, index: usize, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        if i16::try_from(value).is_err() {
+            return Err(AssemblerError::IntegerOutOfRange {
+                line,
+                column,
+                value,
+            });
+        }
+        self.push_u16(i64::from(value as u16), index, results)
+    }
+
+    fn push_u16(&self, value: i64, index: usize, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+        let (line, column) = self.operand_position(index);
+        if value < 0 || value > i64::from(u16::MAX) {
+            return Err(AssemblerError::IntegerOutOfRange {
+                line,
+                column,
+                value,
+            });
+        }
+        results.push((value >> 8) as u8);
+        results.push(value as u8);
+        Ok(())
     }
 }
 
@@ -134,7 +508,11 @@ mod tests {
                     directive: None,
                     operand1: Some(Token::Register { reg_num: 0 }),
                     operand2: Some(Token::IntegerOperand { value: 100 }),
-                    operand3: None
+                    operand3: None,
+                    mnemonic: "load".to_string(),
+                    line: 1,
+                    column: 1,
+                    operand_positions: vec![],
                 }
             ))
         );
@@ -154,7 +532,11 @@ mod tests {
                     directive: None,
                     operand1: None,
                     operand2: None,
-                    operand3: None
+                    operand3: None,
+                    mnemonic: "hlt".to_string(),
+                    line: 1,
+                    column: 1,
+                    operand_positions: vec![],
                 }
             ))
         );
@@ -174,10 +556,101 @@ mod tests {
                     operand1: Some(Token::Register { reg_num: 0 }),
                     operand2: Some(Token::Register { reg_num: 1 }),
                     operand3: Some(Token::Register { reg_num: 2 }),
+                    mnemonic: "add".to_string(),
+                    line: 1,
+                    column: 1,
+                    operand_positions: vec![],
                 }
             ))
         );
     }
 
 
-}
\ No newline at end of file
+
+    #[test]
+    fn test_heap_offset_is_one_byte() {
+        let (_, instruction) = instruction_combined(CompleteStr("storew $1 $2 #8")).unwrap();
+        let bytes = instruction.to_bytes(&SymbolTable::new(), 0).unwrap();
+        assert_eq!(bytes, vec![Opcode::STOREW.into(), 1, 2, 8]);
+        let (_, instruction) = instruction_combined(CompleteStr("loadb $1 $2 #256")).unwrap();
+        assert_eq!(
+            instruction.to_bytes(&SymbolTable::new(), 0),
+            Err(vec![AssemblerError::IntegerOutOfRange { line: 1, column: 1, value: 256 }])
+        );
+    }
+
+    #[test]
+    fn test_load_immediate_range() {
+        let bytes = |s| {
+            let (_, instruction) = instruction_combined(CompleteStr(s)).unwrap();
+            instruction.to_bytes(&SymbolTable::new(), 0)
+        };
+        assert_eq!(bytes("load $0 #-1"), Ok(vec![0, 0, 0xff, 0xff]));
+        assert_eq!(bytes("load $0 #0x7fff"), Ok(vec![0, 0, 0x7f, 0xff]));
+        assert_eq!(
+            bytes("load $0 #0x8000"),
+            Err(vec![AssemblerError::IntegerOutOfRange { line: 1, column: 1, value: 0x8000 }])
+        );
+        assert_eq!(
+            bytes("load $0 #-32769"),
+            Err(vec![AssemblerError::IntegerOutOfRange { line: 1, column: 1, value: -32769 }])
+        );
+        assert_eq!(bytes("lui $0 #0xffff"), Ok(vec![47, 0, 0xff, 0xff]));
+        assert_eq!(bytes("lui $0 #-1"), Ok(vec![47, 0, 0xff, 0xff]));
+        assert_eq!(
+            bytes("lui $0 #0x10000"),
+            Err(vec![AssemblerError::IntegerOutOfRange { line: 1, column: 1, value: 0x10000 }])
+        );
+    }
+
+    #[test]
+    fn test_operand_signature() {
+        let bytes = |s| {
+            let (_, instruction) = instruction_combined(CompleteStr(s)).unwrap();
+            instruction.to_bytes(&SymbolTable::new(), 0)
+        };
+        assert_eq!(
+            bytes("add $0 #5"),
+            Err(vec![AssemblerError::WrongOperandCount { line: 1, column: 1, found: 2, expected: 3 }])
+        );
+        assert_eq!(
+            bytes("hlt $1"),
+            Err(vec![AssemblerError::WrongOperandCount { line: 1, column: 1, found: 1, expected: 0 }])
+        );
+        assert_eq!(
+            bytes("add $0 #5 $1"),
+            Err(vec![AssemblerError::BadOperandKind {
+                line: 1,
+                column: 1,
+                operand: Token::IntegerOperand { value: 5 },
+                expected: "a register",
+            }])
+        );
+        assert_eq!(
+            bytes("loadb $0 $1 @x"),
+            Err(vec![AssemblerError::BadOperandKind {
+                line: 1,
+                column: 1,
+                operand: Token::LabelUsage { name: "x".to_string() },
+                expected: "an integer",
+            }])
+        );
+        assert_eq!(bytes("inc $3"), Ok(vec![18, 3, 0, 0]));
+        assert_eq!(bytes("xor $0 $1 $2"), Ok(vec![36, 0, 1, 2]));
+        assert_eq!(bytes("not $0 $1"), Ok(vec![37, 0, 1, 0]));
+    }
+
+    #[test]
+    fn test_load_immediate_expansion() {
+        let (_, short) = statement(CompleteStr("li $1 #-5")).unwrap();
+        assert_eq!(short.len(), 1);
+        assert_eq!(short[0].to_bytes(&SymbolTable::new(), 0), Ok(vec![0, 1, 0xff, 0xfb]));
+
+        let (_, wide) = statement(CompleteStr("big: li $1 #0x12348000")).unwrap();
+        assert_eq!(wide.len(), 2);
+        assert_eq!(wide[0].get_label_name(), Some("big".to_string()));
+        assert_eq!(wide[0].to_bytes(&SymbolTable::new(), 0), Ok(vec![0, 1, 0x80, 0x00]));
+        assert_eq!(wide[1].to_bytes(&SymbolTable::new(), 4), Ok(vec![47, 1, 0x12, 0x34]));
+        assert!(!wide[1].is_label());
+    }
+}
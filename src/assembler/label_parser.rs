@@ -4,7 +4,7 @@ use nom::{alphanumeric, multispace};
 
 // Looks for a user-defined label, such as `label1:`
 named!(pub label_declaration<CompleteStr, Token>,
-    ws!(
+    ws_comments!(
         do_parse!(
             name: alphanumeric >>
             tag!(":") >>
@@ -18,7 +18,7 @@ named!(pub label_declaration<CompleteStr, Token>,
 
 // Looks for a user-defined label, such as `label1:`
 named!(pub label_usage<CompleteStr, Token>,
-    ws!(
+    ws_comments!(
         do_parse!(
             tag!("@") >>
             name: alphanumeric >>
@@ -32,6 +32,8 @@ named!(pub label_usage<CompleteStr, Token>,
 
 #[cfg(test)]
 mod tests {
+    #![allow(clippy::bool_assert_comparison)]
+
     use super::*;
 
     #[test]
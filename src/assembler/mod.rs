@@ -1,4 +1,24 @@
 use crate::instruction::Opcode;
+
+// Like nom's `ws!`, but also skips `;`, `#!` and `/* */` comments between and after the tokens
+macro_rules! ws_comments (
+    ($i:expr, $($args:tt)*) => (
+        {
+            use nom::{Convert, Err};
+            use crate::assembler::comment_parser::space_or_comment;
+
+            match sep!($i, space_or_comment, $($args)*) {
+                Err(e) => Err(e),
+                Ok((i1, o)) => match space_or_comment(i1) {
+                    Err(e) => Err(Err::convert(e)),
+                    Ok((i2, _)) => Ok((i2, o)),
+                },
+            }
+        }
+    )
+);
+
+pub mod comment_parser;
 pub mod opcode_parser;
 pub mod instruction_parsers;
 pub mod program_parser;
@@ -6,11 +26,11 @@ pub mod register_parser;
 pub mod operand_parser;
 pub mod label_parser;
 pub mod directive_parser;
-use nom::types::CompleteStr;
-use crate::assembler::program_parser::{program, Program};
-use crate::vm;
-const PIE_HEADER_PREFIX: [u8; 4] = [45, 50, 49, 45];
-const PIE_HEADER_LENGTH: usize = 64;
+pub mod assembler_errors;
+use std::fmt;
+use crate::assembler::program_parser::{parse_program, Program};
+use crate::assembler::assembler_errors::AssemblerError;
+use crate::pie;
 // use crate::assembler::opcode::opcode_parsers;
 // use crate::opcode::operand_parsers;
 // use crate::opcode::register_parsers;
@@ -19,24 +39,47 @@ const PIE_HEADER_LENGTH: usize = 64;
 pub enum Token {
     Op{code: Opcode},
     Register{reg_num: u8},
+    FloatRegister{reg_num: u8},
     IntegerOperand{value: i32},
+    FloatOperand{value: f64},
     LabelDeclaration { name: String },
     LabelUsage { name: String },
-    Directive { name: String }
+    Directive { name: String },
+    IrString { name: String }
 }
 
 
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Op { code } => write!(f, "{}", code.mnemonic()),
+            Token::Register { reg_num } => write!(f, "${}", reg_num),
+            Token::FloatRegister { reg_num } => write!(f, "$f{}", reg_num),
+            Token::IntegerOperand { value } => write!(f, "#{}", value),
+            // Debug formatting always keeps the decimal point, so the literal reads back as a float
+            Token::FloatOperand { value } => write!(f, "#{:?}", value),
+            Token::LabelDeclaration { name } => write!(f, "{}:", name),
+            Token::LabelUsage { name } => write!(f, "@{}", name),
+            Token::Directive { name } => write!(f, ".{}", name),
+            Token::IrString { name } => write!(f, "'{}'", name),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Assembler {
     pub phase: AssemblerPhase,
-    pub symbols: SymbolTable
+    pub symbols: SymbolTable,
+    // Errors found so far in the current `assemble` call
+    pub errors: Vec<AssemblerError>
 }
 
 impl Assembler {
     pub fn new() -> Assembler {
         Assembler {
             phase: AssemblerPhase::First,
-            symbols: SymbolTable::new()
+            symbols: SymbolTable::new(),
+            errors: vec![]
         }
     }
     // The assemble function accepts a raw string reference
@@ -56,68 +99,121 @@ impl Assembler {
     // The second phase is then called, which just calls to_bytes on every AssemblerInstruction
     
     // All the bytes are added to a Vec<u8> which contains the fully assembled bytecode
-    pub fn assemble(&mut self, raw: &str) -> Option<Vec<u8>> {
-        match program(CompleteStr(raw)) {
-            Ok((_remainder, program)) => {
-                // First get the header so we can smush it into the bytecode letter
-            let mut assembled_program = self.write_pie_header();
-            self.process_first_phase(&program);
-            let mut body = self.process_second_phase(&program);
-
-            // Merge the header with the populated body vector
-            assembled_program.append(&mut body);
-            Some(assembled_program)
-            },
-            Err(e) => {
-                println!("There was an error assembling the code: {:?}", e);
-                None
-            }
+
+    // Every error found along the way is collected, so one call reports all of them
+    pub fn assemble(&mut self, raw: &str) -> Result<Vec<u8>, Vec<AssemblerError>> {
+        self.phase = AssemblerPhase::First;
+        self.symbols = SymbolTable::new();
+        self.errors = vec![];
+        let program = match parse_program(raw) {
+            Ok(program) => program,
+            Err(e) => return Err(vec![e]),
+        };
+        let code_start = self.process_first_phase(&program);
+        let (mut data, mut code) = self.process_second_phase(&program, code_start);
+        if !self.errors.is_empty() {
+            // The phases find errors in different passes, so put them back in source order
+            self.errors.sort_by_key(|e| (e.line(), e.column()));
+            return Err(self.errors.clone());
         }
+
+        // First get the header so we can smush it into the bytecode letter, then the data
+        // section followed by the code
+        let mut assembled_program = self.write_pie_header(data.len());
+        assembled_program.append(&mut data);
+        assembled_program.append(&mut code);
+        Ok(assembled_program)
     }
     
-    //first label extract labels and pas it onto the second label
-    fn process_first_phase(&mut self, p: &Program) {
-        self.extract_labels(p);
+    //first label extract labels and pas it onto the second label, returning where the code starts
+    fn process_first_phase(&mut self, p: &Program) -> u32 {
+        let code_start = self.extract_labels(p);
         self.phase = AssemblerPhase::Second;
+        code_start
     }
     
-    //second label converts it to byte and return the vector program
-    fn process_second_phase(&mut self, p: &Program) -> Vec<u8> {
-        let mut program = vec![];
+    //second label converts it to byte and return the data and code sections
+    fn process_second_phase(&mut self, p: &Program, code_start: u32) -> (Vec<u8>, Vec<u8>) {
+        // Programs without section directives are all code
+        let mut section = Section::Code;
+        let mut data = vec![];
+        let mut code = vec![];
         for i in &p.instructions {
-            let mut bytes = i.to_bytes();
-            program.append(&mut bytes);
-
+            match i.directive_name() {
+                Some("data") => section = Section::Data,
+                Some("code") => section = Section::Code,
+                Some(_) => {
+                    if section != Section::Data {
+                        self.errors.push(AssemblerError::WrongSection { line: i.line, column: i.column });
+                    }
+                    match i.data_bytes() {
+                        Ok(mut bytes) => data.append(&mut bytes),
+                        Err(e) => self.errors.push(e),
+                    }
+                },
+                None => {
+                    if section != Section::Code {
+                        self.errors.push(AssemblerError::WrongSection { line: i.line, column: i.column });
+                    }
+                    let address = code_start + code.len() as u32;
+                    match i.to_bytes(&self.symbols, address) {
+                        Ok(mut bytes) => code.append(&mut bytes),
+                        Err(mut errors) => self.errors.append(&mut errors),
+                    }
+                }
+            }
         }
-        program
+        (data, code)
     }
-    //extract labels
-    fn extract_labels(&mut self, p: &Program) {
-        let mut c = 0;
+    //extract labels. Code labels are offset by the header and data so they hold the address the
+    //VM will see, and data labels hold the heap address the data is loaded at.
+    fn extract_labels(&mut self, p: &Program) -> u32 {
+        let data_length: usize = p.instructions.iter().map(|i| i.data_size()).sum();
+        let code_start = (pie::PIE_HEADER_LENGTH + data_length) as u32;
+        let mut data_offset = 0;
+        let mut code_offset = code_start;
         for i in &p.instructions {
-            if i.is_label() {
-                match i.get_label_name() {
-                    Some(name) => {
-                        let symbol = Symbol::new(name, SymbolType::Label, c);
-                        self.symbols.add_symbol(symbol);
-                    },
-                    None => {}
-                };
+            let (symbol_type, offset) = if i.directive.is_some() {
+                (SymbolType::Data, data_offset)
+            } else {
+                (SymbolType::Label, code_offset)
+            };
+            if let Some(name) = i.get_label_name() {
+                if matches!(i.directive_name(), Some("data") | Some("code")) {
+                    self.errors.push(AssemblerError::LabelOnSection {
+                        line: i.line,
+                        column: i.column,
+                        name,
+                    });
+                } else if self.symbols.symbol_value(&name).is_some() {
+                    self.errors.push(AssemblerError::DuplicateLabel {
+                        line: i.line,
+                        column: i.column,
+                        name,
+                    });
+                } else {
+                    let symbol = Symbol::new(name, symbol_type, offset);
+                    self.symbols.add_symbol(symbol);
+                }
+            }
+            if i.directive.is_some() {
+                data_offset += i.data_size() as u32;
+            } else {
+                code_offset += 4;
             }
-            c += 4;
         }
+        code_start
     }
-    //This will write out our header, which right now is 4 bytes and 60 0s.
+    //This will write out our header, which right now is 4 bytes, the data length and 0s.
     // Its important to pad the header so that we can use those bytes later if needed.
-    fn write_pie_header(&self) -> Vec<u8> {
-        let mut header = vec![];
-        for byte in PIE_HEADER_PREFIX.iter() {
-            header.push(byte.clone());
-        }
-        while header.len() <= PIE_HEADER_LENGTH {
-            header.push(0 as u8);
-        }
-        header
+    fn write_pie_header(&self, data_length: usize) -> Vec<u8> {
+        pie::write_header(data_length)
+    }
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -138,9 +234,18 @@ impl Symbol {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum SymbolType {
     Label,
+    // A label on a data directive, holding a heap address
+    Data,
+}
+
+// The part of the image instructions are assembled into, switched with `.data` and `.code`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    Data,
+    Code,
 }
 
 #[derive(Debug)]
@@ -167,6 +272,28 @@ impl SymbolTable {
         }
         None
     }
+
+    pub fn symbol_type(&self, s: &str) -> Option<&SymbolType> {
+        self.symbols.iter().find(|symbol| symbol.name == s).map(|symbol| &symbol.symbol_type)
+    }
+
+    // The names and addresses of labels in the code, leaving out data labels
+    pub fn code_labels(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.symbol_type == SymbolType::Label)
+            .map(|symbol| (symbol.name.as_str(), symbol.offset))
+    }
+
+    pub fn label_at(&self, address: u32) -> Option<&str> {
+        self.code_labels().find(|(_, offset)| *offset == address).map(|(name, _)| name)
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -176,11 +303,15 @@ pub enum AssemblerPhase {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_symbol_table() {
     let mut sym = SymbolTable::new();
     let new_symbol = Symbol::new("test".to_string(), SymbolType::Label, 12);
     sym.add_symbol(new_symbol);
     assert_eq!(sym.symbols.len(), 1);
+    sym.add_symbol(Symbol::new("bytes".to_string(), SymbolType::Data, 12));
+    assert_eq!(sym.label_at(12), Some("test"));
+    assert_eq!(sym.code_labels().count(), 1);
     let v = sym.symbol_value("test");
     assert_eq!(true, v.is_some());
     let v = v.unwrap();
@@ -194,8 +325,269 @@ fn test_assemble_program() {
     let mut asm = Assembler::new();
     let test_string = "load $0 #100\nload $1 #1\nload $2 #0\ntest: inc $0\nneq $0 $2\njmpe @test\nhlt";
     let program = asm.assemble(test_string).unwrap();
-    let mut vm = vm::VM::new();
-    assert_eq!(program.len(), 89);
+    let mut vm = crate::vm::VM::new();
+    assert_eq!(program.len(), 92);
     vm.add_bytes(program);
-    assert_eq!(vm.program.len(), 89);
-}
\ No newline at end of file
+    assert_eq!(vm.program.len(), 92);
+}
+
+#[test]
+fn test_assemble_label_operands() {
+    let mut asm = Assembler::new();
+    let test_string = "load $0 #1\ntest: jmp @test\njmpe @test\njmpf @end\nhlt\nend: hlt";
+    let program = asm.assemble(test_string).unwrap();
+    let code = &program[program.len() - 24..];
+    let test = asm.symbols.symbol_value("test").unwrap();
+    assert_eq!(test, program.len() as u32 - 20);
+    assert_eq!(&code[4..8], &[Opcode::JMPI.into(), (test >> 8) as u8, test as u8, 0]);
+    assert_eq!(&code[8..12], &[Opcode::JEQI.into(), (test >> 8) as u8, test as u8, 0]);
+    // `end` is one instruction past the end of the `jmpf`
+    assert_eq!(&code[12..16], &[Opcode::JMPFI.into(), 0, 4, 0]);
+}
+
+
+#[test]
+fn test_assemble_collects_errors() {
+    let mut asm = Assembler::new();
+    let test_string = "load $0 #70000\nfoo $1\ntest: jmp @nowhere\ntest: hlt";
+    let errors = asm.assemble(test_string).unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            AssemblerError::IntegerOutOfRange { line: 1, column: 9, value: 70000 },
+            AssemblerError::UnknownOpcode { line: 2, column: 1, mnemonic: "foo".to_string() },
+            AssemblerError::UndefinedLabel { line: 3, column: 11, name: "nowhere".to_string() },
+            AssemblerError::DuplicateLabel { line: 4, column: 1, name: "test".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_error_positions() {
+    let mut asm = Assembler::new();
+    let errors = asm.assemble("li $0 @x
+start: add $0 /* five */ #5 $1").unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            AssemblerError::BadOperandKind {
+                line: 1,
+                column: 7,
+                operand: Token::LabelUsage { name: "x".to_string() },
+                expected: "an integer",
+            },
+            AssemblerError::BadOperandKind {
+                line: 2,
+                column: 26,
+                operand: Token::IntegerOperand { value: 5 },
+                expected: "a register",
+            },
+        ]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "line 1, column 7: operand `@x` is not allowed here, expected an integer"
+    );
+    assert_eq!(
+        asm.assemble("li $0"),
+        Err(vec![AssemblerError::WrongOperandCount { line: 1, column: 1, found: 1, expected: 2 }])
+    );
+    assert_eq!(
+        asm.assemble("li $40 #0x12345"),
+        Err(vec![AssemblerError::UnknownRegister { line: 1, column: 4, operand: Token::Register { reg_num: 40 } }])
+    );
+}
+
+#[test]
+fn test_assemble_unknown_register() {
+    let mut asm = Assembler::new();
+    let errors = asm.assemble("add $40 $1 $2\nhlt\naddf $f0 $f1 $f32").unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            AssemblerError::UnknownRegister { line: 1, column: 5, operand: Token::Register { reg_num: 40 } },
+            AssemblerError::UnknownRegister {
+                line: 3,
+                column: 14,
+                operand: Token::FloatRegister { reg_num: 32 },
+            },
+        ]
+    );
+    assert_eq!(errors[0].to_string(), "line 1, column 5: there is no register $40");
+    assert!(asm.assemble("add $31 $0 $0\naddf $f31 $f0 $f0").is_ok());
+}
+
+#[test]
+fn test_run_assembled_loop() {
+    let mut asm = Assembler::new();
+    let test_string = "load $0 #0\nload $1 #5\ntest: inc $0\nneq $0 $1\njmpe @test\nhlt";
+    let program = asm.assemble(test_string).unwrap();
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[0], 5);
+}
+
+#[test]
+fn test_assemble_call() {
+    let mut asm = Assembler::new();
+    let test_string = "load $0 #3\ncall @double\nhlt\ndouble: add $0 $0 $0\nret";
+    let program = asm.assemble(test_string).unwrap();
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[0], 6);
+}
+
+#[test]
+fn test_assemble_relative_jumps() {
+    let mut asm = Assembler::new();
+    let test_string = "load $0 #0\nload $1 #3\nback: inc $0\nneq $0 $1\njmpf @check\nhlt\ncheck: jmpe @again\nhlt\nagain: jmpb @back";
+    let program = asm.assemble(test_string).unwrap();
+    let code = &program[crate::pie::PIE_HEADER_LENGTH..];
+    assert_eq!(&code[16..20], &[Opcode::JMPFI.into(), 0, 4, 0]);
+    assert_eq!(&code[32..36], &[Opcode::JMPBI.into(), 0, 28, 0]);
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[0], 3);
+
+    let errors = asm.assemble("load $0 #1\nend: inc $0\njmpf @end").unwrap_err();
+    assert_eq!(errors, vec![AssemblerError::IntegerOutOfRange { line: 3, column: 6, value: -8 }]);
+}
+
+#[test]
+fn test_assemble_data_section() {
+    let mut asm = Assembler::new();
+    let test_string = ".data\nhello: .asciiz 'Hi'\nnums: .byte #1 #-1\n.word #0x01020304\n.space #2\n.code\nload $0 @nums\nloadb $1 $0 #1\nloadw $2 $0 #2\nhlt";
+    let program = asm.assemble(test_string).unwrap();
+    assert_eq!(pie::data_length(&program), 11);
+    assert_eq!(&program[pie::PIE_HEADER_LENGTH..pie::PIE_HEADER_LENGTH + 11], &[72, 105, 0, 1, 255, 1, 2, 3, 4, 0, 0]);
+    assert_eq!(asm.symbols.symbol_value("hello"), Some(0));
+    assert_eq!(asm.symbols.symbol_value("nums"), Some(3));
+    assert_eq!(asm.symbols.symbol_type("nums"), Some(&SymbolType::Data));
+
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[1], 255);
+    assert_eq!(vm.registers[2], 0x0102_0304);
+}
+
+#[test]
+fn test_assemble_section_errors() {
+    let mut asm = Assembler::new();
+    let errors = asm.assemble(".asciiz 'a'\n.data\n.bogus #1\n.byte #300\nhlt").unwrap_err();
+    assert_eq!(
+        errors,
+        vec![
+            AssemblerError::WrongSection { line: 1, column: 1 },
+            AssemblerError::UnknownDirective { line: 3, column: 1, name: "bogus".to_string() },
+            AssemblerError::IntegerOutOfRange { line: 4, column: 7, value: 300 },
+            AssemblerError::WrongSection { line: 5, column: 1 },
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_data_label_targets() {
+    let mut asm = Assembler::new();
+    let test_string = ".data\nmsg: .asciiz 'hi'\nstart: .code\nload $0 @msg\njmp @msg\nbeq $0 $1 @msg\njmp @start";
+    let errors = asm.assemble(test_string).unwrap_err();
+    let msg = Token::LabelUsage { name: "msg".to_string() };
+    assert_eq!(
+        errors,
+        vec![
+            AssemblerError::LabelOnSection { line: 3, column: 1, name: "start".to_string() },
+            AssemblerError::BadOperandKind { line: 5, column: 5, operand: msg.clone(), expected: "a code label" },
+            AssemblerError::BadOperandKind { line: 6, column: 11, operand: msg, expected: "a code label" },
+            AssemblerError::UndefinedLabel { line: 7, column: 5, name: "start".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_comments() {
+    let mut asm = Assembler::new();
+    let test_string = "; double three\nload $0 #3 /* argument */\ncall @double\nhlt #! stop here\ndouble: ; label-only line\n  add $0 $0 $0\nret\n";
+    let program = asm.assemble(test_string).unwrap();
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[0], 6);
+
+    let errors = asm.assemble("load $0 #3\n  hlt\n  ?oops\nhlt").unwrap_err();
+    assert_eq!(errors[0].line(), 3);
+    assert_eq!(errors[0].column(), 3);
+    let errors = asm.assemble("hlt\n/* never closed").unwrap_err();
+    assert_eq!(
+        errors[0].to_string(),
+        "line 2, column 1: unexpected `/* never closed`, expected `*/` to close the block comment"
+    );
+}
+
+#[test]
+fn test_assemble_load_immediate() {
+    let mut asm = Assembler::new();
+    let test_string = "li $0 #-100000\nli $1 #7\nmov $0 $2\nend: jmp @end";
+    let program = asm.assemble(test_string).unwrap();
+    // The wide constant takes two instructions, which moves the label along
+    assert_eq!(asm.symbols.symbol_value("end"), Some(pie::PIE_HEADER_LENGTH as u32 + 16));
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    for _ in 0..4 {
+        vm.run_once().unwrap();
+    }
+    assert_eq!(vm.registers[0], -100_000);
+    assert_eq!(vm.registers[1], 7);
+    assert_eq!(vm.registers[2], -100_000);
+}
+
+#[test]
+fn test_assemble_floats() {
+    let mut asm = Assembler::new();
+    let test_string = ".data\nconsts: .double #1.5 #-0.25 #3\n.code\nload $0 @consts\nloadf $f0 $0 #0\nloadf $f1 $0 #8\nloadf $f2 $0 #16\nmulf $f0 $f2 $f3\naddf $f3 $f1 $f3\nftoi $f3 $1\nitof $1 $f4\nltf $f4 $f3\nhlt";
+    let program = asm.assemble(test_string).unwrap();
+    assert_eq!(pie::data_length(&program), 24);
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.float_registers[3], 4.25);
+    assert_eq!(vm.registers[1], 4);
+    assert_eq!(vm.float_registers[4], 4.0);
+
+    let errors = asm.assemble("addf $f0 $1 $f2\nload $0 #1.5\nloadf $f0 #-2.5").unwrap_err();
+    assert_eq!(
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        vec![
+            "line 1, column 10: operand `$1` is not allowed here, expected a float register",
+            "line 2, column 9: float literal #1.5 cannot be an instruction operand, declare it with .double and read it with loadf",
+            "line 3, column 11: float literal #-2.5 cannot be an instruction operand, declare it with .double and read it with loadf",
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_conditional_branches() {
+    let mut asm = Assembler::new();
+    // Sums 1 to 10, counting down with a fused branch and stopping with a flag branch
+    let test_string = "li $0 #10\nli $1 #0\nli $2 #0\nloop: add $1 $0 $1\ndec $0\nbgt $0 $2 @loop\neq $1 $2\nbt @fail\nli $3 #1\nhlt\nfail: li $3 #-1";
+    let program = asm.assemble(test_string).unwrap();
+    let code = &program[pie::PIE_HEADER_LENGTH..];
+    assert_eq!(&code[20..24], &[Opcode::BGT.into(), 0, 2, (-12i8) as u8]);
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+    assert_eq!(vm.registers[1], 55);
+    assert_eq!(vm.registers[3], 1);
+
+    // Both kinds of branch count bytes: bt skips the hlt and bge comes back to it
+    let program = asm.assemble("eq $0 $0\nbt #4\nhlt\nbge $0 $1 #-8").unwrap();
+    let mut vm = crate::vm::VM::new();
+    vm.load_program(program).unwrap();
+    assert_eq!(vm.run(), Ok(crate::vm::ExitReason::Halted));
+
+    let far = format!("top: hlt\n{}blt $0 $1 @top", "nop\n".repeat(31));
+    let errors = asm.assemble(&far).unwrap_err();
+    assert_eq!(errors, vec![AssemblerError::IntegerOutOfRange { line: 33, column: 11, value: -132 }]);
+}
@@ -1,9 +1,9 @@
 use nom::types::CompleteStr;
-use nom::digit;
+use std::convert::TryFrom;
+use nom::{digit, hex_digit};
 use nom::alpha1;
 use crate::assembler::Token;
 use crate::instruction;
-use crate::assembler::register_parser;
 //recognize opcode string from instructions
 named!(pub opcode<CompleteStr, Token>,
     do_parse!(
@@ -19,20 +19,60 @@ named!(pub opcode<CompleteStr, Token>,
   
 
 
+// Integers can be negative and written in decimal, hex (`#0xff`) or binary (`#0b1010`), as long
+// as they fit in an i32
 named!(pub integer_operand<CompleteStr, Token>,
-    ws!(
+    ws_comments!(
         do_parse!(
             tag!("#") >>
-            reg_num: digit >>
+            sign: opt!(tag!("-")) >>
+            magnitude: alt!(
+                preceded!(tag_no_case!("0x"), map_res!(hex_digit, |d: CompleteStr| i64::from_str_radix(&d, 16))) |
+                preceded!(tag_no_case!("0b"), map_res!(is_a!("01"), |d: CompleteStr| i64::from_str_radix(&d, 2))) |
+                map_res!(digit, |d: CompleteStr| d.parse::<i64>())
+            ) >>
+            value: expr_opt!(i32::try_from(if sign.is_some() { -magnitude } else { magnitude }).ok()) >>
             (
-                Token::IntegerOperand{value: reg_num.parse::<i32>().unwrap()}
+                Token::IntegerOperand{value}
             )
         )
     )
 );
 
+// The digits of a float literal, which need a decimal point or an exponent to tell them apart
+// from an integer, such as `-1.5` or `2e10`
+named!(float_literal<CompleteStr, CompleteStr>,
+    verify!(
+        recognize!(
+            tuple!(
+                opt!(tag!("-")),
+                digit,
+                opt!(pair!(tag!("."), digit)),
+                opt!(tuple!(tag_no_case!("e"), opt!(alt!(tag!("+") | tag!("-"))), digit))
+            )
+        ),
+        |s: CompleteStr| s.contains('.') || s.contains('e') || s.contains('E')
+    )
+);
 
+// A float literal, such as `#1.5` or `#-2.5e-3`. Instructions have no room for a float, so only
+// `.double` takes these, and code reads them from the heap with LOADF.
+named!(pub float_operand<CompleteStr, Token>,
+    ws_comments!(
+        do_parse!(
+            tag!("#") >>
+            value: map_res!(float_literal, |s: CompleteStr| s.parse::<f64>()) >>
+            (
+                Token::FloatOperand{value}
+            )
+        )
+    )
+);
+
+#[cfg(test)]
 mod tests {
+    #![allow(clippy::bool_assert_comparison)]
+
     use super::*;
 
     #[test]
@@ -52,6 +92,7 @@ mod tests {
     
 
     #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_parse_integer_operand() {
     // Test a valid integer operand
     let result = integer_operand(CompleteStr("#10"));
@@ -66,6 +107,19 @@ fn test_parse_integer_operand() {
 }
 
 #[test]
+fn test_parse_integer_operand_forms() {
+    let value = |s| integer_operand(CompleteStr(s)).map(|(_, token)| token);
+    assert_eq!(value("#-1"), Ok(Token::IntegerOperand{value: -1}));
+    assert_eq!(value("#0xFF"), Ok(Token::IntegerOperand{value: 255}));
+    assert_eq!(value("#-0x10"), Ok(Token::IntegerOperand{value: -16}));
+    assert_eq!(value("#0b1010"), Ok(Token::IntegerOperand{value: 10}));
+    assert_eq!(value("#0"), Ok(Token::IntegerOperand{value: 0}));
+    assert_eq!(value("#-2147483648"), Ok(Token::IntegerOperand{value: i32::MIN}));
+    assert!(value("#2147483648").is_err());
+}
+
+#[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_opcode() {
     let result = opcode(CompleteStr("load"));
     assert_eq!(result.is_ok(), true);
@@ -79,4 +133,13 @@ fn test_opcode() {
     let (_, token) = result.unwrap();
     assert_eq!(token, Token::Op { code: instruction::Opcode::DIV });
 }
-}
\ No newline at end of file
+
+#[test]
+fn test_parse_float_operand() {
+    assert_eq!(float_operand(CompleteStr("#1.5")), Ok((CompleteStr(""), Token::FloatOperand { value: 1.5 })));
+    assert_eq!(float_operand(CompleteStr("#-2.5e-3")), Ok((CompleteStr(""), Token::FloatOperand { value: -0.0025 })));
+    assert_eq!(float_operand(CompleteStr("#1e20")), Ok((CompleteStr(""), Token::FloatOperand { value: 1e20 })));
+    assert!(float_operand(CompleteStr("#15")).is_err());
+    assert!(float_operand(CompleteStr("#0x1e5")).is_err());
+}
+}
@@ -1,13 +1,77 @@
-use crate::assembler::register_parser::register;
+use crate::assembler::register_parser::{float_register, register};
+use crate::assembler::comment_parser::space_or_comment;
 use crate::assembler::Token;
-use nom::digit;
 use nom::types::CompleteStr;
-use crate::assembler::opcode_parser::integer_operand;
+use nom::IResult;
+use crate::assembler::opcode_parser::{float_operand, integer_operand};
+use crate::assembler::label_parser::label_usage;
 
 
+// A string constant in single quotes, such as `'Hello'`
+named!(pub irstring<CompleteStr, Token>,
+    ws_comments!(
+        do_parse!(
+            tag!("'") >>
+            content: take_until!("'") >>
+            tag!("'") >>
+            (
+                Token::IrString{name: content.to_string()}
+            )
+        )
+    )
+);
+
+// Float literals and registers are tried first, as their integer counterparts would match a
+// prefix of them
 named!(pub operand<CompleteStr, Token>,
     alt!(
+        float_operand |
         integer_operand |
-        register
+        label_usage |
+        float_register |
+        register |
+        irstring
     )
 );
+
+fn input_length(input: CompleteStr) -> IResult<CompleteStr, usize> {
+    Ok((input, input.len()))
+}
+
+// An operand along with how much input was left where it starts, which the program parser turns
+// into the operand's line and column
+named!(pub located_operand<CompleteStr, (usize, Token)>,
+    do_parse!(
+        space_or_comment >>
+        start: input_length >>
+        token: operand >>
+        (
+            (start, token)
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label_usage_operand() {
+        let result = operand(CompleteStr("@loop"));
+        assert_eq!(result, Ok((CompleteStr(""), Token::LabelUsage { name: "loop".to_string() })));
+    }
+
+    #[test]
+    fn test_parse_string_operand() {
+        let result = operand(CompleteStr("'Hello, world'"));
+        assert_eq!(result, Ok((CompleteStr(""), Token::IrString { name: "Hello, world".to_string() })));
+        let result = irstring(CompleteStr("'unterminated"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_located_operand() {
+        let result = located_operand(CompleteStr(" /* x */ $1 #2"));
+        assert_eq!(result, Ok((CompleteStr("#2"), (5, Token::Register { reg_num: 1 }))));
+    }
+}
@@ -1,6 +1,11 @@
 use nom::types::CompleteStr;
+use nom::{IResult, ErrorKind};
 
-use crate::assembler::instruction_parsers::{AssemblerInstruction, instruction};
+use crate::assembler::instruction_parsers::{AssemblerInstruction, located_statement};
+use crate::assembler::assembler_errors::AssemblerError;
+use crate::assembler::comment_parser::space_or_comment;
+use crate::assembler::label_parser::label_declaration;
+use crate::assembler::SymbolTable;
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
@@ -8,27 +13,110 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, Vec<AssemblerError>> {
         let mut program = vec![];
+        let mut errors = vec![];
         for instruction in &self.instructions {
-            program.append(&mut instruction.to_bytes());
+            let address = program.len() as u32;
+            match instruction.to_bytes(symbols, address) {
+                Ok(mut bytes) => program.append(&mut bytes),
+                Err(mut e) => errors.append(&mut e),
+            }
+        }
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// Turns a byte offset into `source` into a 1-based line and column
+pub fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    advance_position((1, 1), &source[..offset])
+}
+
+// Moves a 1-based line and column past `consumed`, so a parser can keep track of where it is
+// without rescanning the source from the start
+fn advance_position((mut line, mut column): (usize, usize), consumed: &str) -> (usize, usize) {
+    for c in consumed.chars() {
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
         }
-        program
     }
+    (line, column)
 }
-//create a program parser from the assembler instruction in the opcode parser
-named!(pub program<CompleteStr, Program>,
-    do_parse!(
-        instructions: many1!(instruction) >>
-        (
-            Program {
-                instructions: instructions
+
+//create a program parser from the assembler instruction in the opcode parser, recording where
+//each instruction starts so errors can point back at the source. Whitespace and comments between
+//instructions are skipped; whatever is left over is returned for the caller to report.
+pub fn program(input: CompleteStr) -> IResult<CompleteStr, Program> {
+    let mut instructions = vec![];
+    let (mut rest, _) = space_or_comment(input)?;
+    let mut position = (1, 1);
+    let mut scanned = 0;
+    while let Ok((remaining, parsed)) = located_statement(rest) {
+        let offset = input.len() - rest.len();
+        position = advance_position(position, &input[scanned..offset]);
+        scanned = offset;
+        let (line, column) = position;
+        for (mut ins, operand_starts) in parsed {
+            ins.line = line;
+            ins.column = column;
+            ins.operand_positions = operand_starts
+                .iter()
+                .map(|start| advance_position(position, &input[offset..input.len() - start]))
+                .collect();
+            instructions.push(ins);
+        }
+        let (remaining, _) = space_or_comment(remaining)?;
+        if remaining.len() == rest.len() {
+            break;
+        }
+        rest = remaining;
+    }
+    if instructions.is_empty() {
+        return Err(nom::Err::Error(error_position!(input, ErrorKind::Many1)));
+    }
+    Ok((rest, Program { instructions }))
+}
+
+// Parses the whole of `source`, failing on the first stretch of input the grammar cannot
+// consume instead of silently dropping everything after it
+pub fn parse_program(source: &str) -> Result<Program, AssemblerError> {
+    let (remainder, last_line) = match program(CompleteStr(source)) {
+        Ok((remainder, p)) => {
+            if remainder.is_empty() {
+                return Ok(p);
             }
-        )
-    )
-);
+            let last_line = p.instructions.last().map(|i| i.line);
+            (remainder, last_line)
+        }
+        // Nothing parsed, so the problem is the first thing that is not whitespace or a comment
+        Err(_) => match space_or_comment(CompleteStr(source)) {
+            Ok((remainder, _)) => (remainder, None),
+            Err(_) => (CompleteStr(source), None),
+        },
+    };
+    let (line, column) = line_and_column(source, source.len() - remainder.len());
+    let found = remainder.lines().next().unwrap_or("").trim_end().to_string();
+    let expected = if remainder.starts_with("/*") {
+        "`*/` to close the block comment"
+    } else if label_declaration(remainder).is_ok() {
+        "an instruction or directive after the label"
+    } else if last_line == Some(line) {
+        "an operand or the end of the line"
+    } else {
+        "an instruction, label or directive"
+    };
+    Err(AssemblerError::ParseError { line, column, found, expected })
+}
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_parse_program() {
     let result = program(CompleteStr("load $0 #100\n"));
     assert_eq!(result.is_ok(), true);
@@ -41,10 +129,70 @@ fn test_parse_program() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_program_to_bytes() {
     let result = program(CompleteStr("load $0 #100\n"));
     assert_eq!(result.is_ok(), true);
     let (_, program) = result.unwrap();
-    let bytecode = program.to_bytes();
+    let bytecode = program.to_bytes(&SymbolTable::new()).unwrap();
     assert_eq!(bytecode.len(), 4);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_program_positions() {
+    let (_, p) = program(CompleteStr("load $0 #100\nadd $0 $1 $2\n  /* pad */ hlt")).unwrap();
+    assert_eq!((p.instructions[1].line, p.instructions[1].column), (2, 1));
+    assert_eq!((p.instructions[2].line, p.instructions[2].column), (3, 13));
+}
+
+#[test]
+fn test_parse_program_with_comments() {
+    let source = "; counts to ten\n#! and stops\nload $0 #0 ; start\nload $1 /* end */ #10\nloop: /* body */\n  inc $0\nneq $0 $1 #! compare\njmpe @loop\nhlt\n/* done */\n";
+    let (leftover, p) = program(CompleteStr(source)).unwrap();
+    assert_eq!(leftover, CompleteStr(""));
+    assert_eq!(p.instructions.len(), 6);
+    assert_eq!((p.instructions[0].line, p.instructions[0].column), (3, 1));
+    assert_eq!((p.instructions[2].line, p.instructions[2].column), (5, 1));
+}
+
+#[test]
+fn test_parse_program_leaves_garbage() {
+    let (leftover, p) = program(CompleteStr("hlt
+load $0 #1
+!!
+hlt")).unwrap();
+    assert_eq!(p.instructions.len(), 2);
+    assert_eq!(leftover, CompleteStr("!!\nhlt"));
+}
+
+#[test]
+fn test_parse_program_requires_full_input() {
+    assert_eq!(parse_program("load $0 #1 ; one\nhlt\n").unwrap().instructions.len(), 2);
+    assert_eq!(
+        parse_program("load $0 #1\nadd $0 $1 %2\nhlt"),
+        Err(AssemblerError::ParseError {
+            line: 2,
+            column: 11,
+            found: "%2".to_string(),
+            expected: "an operand or the end of the line",
+        })
+    );
+    assert_eq!(
+        parse_program("; nothing yet\n  ?? \n"),
+        Err(AssemblerError::ParseError {
+            line: 2,
+            column: 3,
+            found: "??".to_string(),
+            expected: "an instruction, label or directive",
+        })
+    );
+    assert_eq!(
+        parse_program("hlt\nend:"),
+        Err(AssemblerError::ParseError {
+            line: 2,
+            column: 1,
+            found: "end:".to_string(),
+            expected: "an instruction or directive after the label",
+        })
+    );
+}
@@ -3,23 +3,41 @@ use nom::digit;
 use nom::types::CompleteStr;
 
 named!(pub register <CompleteStr, Token>,
-    ws!(
+    ws_comments!(
         do_parse!(
             tag!("$") >>
-            reg_num: digit >>
+            reg_num: map_res!(digit, |d: CompleteStr| d.parse::<u8>()) >>
             (
                 Token::Register{
-                  reg_num: reg_num.parse::<u8>().unwrap()
+                  reg_num
                 }
             )
         )
     )
 );
 
+// A float register, such as `$f3`
+named!(pub float_register <CompleteStr, Token>,
+    ws_comments!(
+        do_parse!(
+            tag!("$f") >>
+            reg_num: map_res!(digit, |d: CompleteStr| d.parse::<u8>()) >>
+            (
+                Token::FloatRegister{
+                  reg_num
+                }
+            )
+        )
+    )
+);
+
+#[cfg(test)]
 mod tests {
     #![allow(unused_imports)]
+    #![allow(clippy::bool_assert_comparison)]
 
-    use super::register;
+    use super::{float_register, register};
+    use crate::assembler::Token;
     use nom::types::CompleteStr;
 
     #[test]
@@ -30,5 +48,15 @@ mod tests {
         assert_eq!(result.is_ok(), false);
         let result = register(CompleteStr("$a"));
         assert_eq!(result.is_ok(), false);
+        let result = register(CompleteStr("$999"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_float_register() {
+        let result = float_register(CompleteStr("$f12"));
+        assert_eq!(result, Ok((CompleteStr(""), Token::FloatRegister { reg_num: 12 })));
+        assert!(float_register(CompleteStr("$12")).is_err());
+        assert!(register(CompleteStr("$f1")).is_err());
     }
 }
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::assembler::SymbolTable;
+use crate::instruction::{Opcode, OperandKind, INSTRUCTION_LENGTH};
+use crate::pie::{self, HeaderError, PIE_HEADER_LENGTH};
+
+/// A decoded operand
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    Register(u8),
+    FloatRegister(u8),
+    Integer(i32),
+    /// A jump target, holding the encoded value as it would be written in assembly, the
+    /// absolute address it points at and whether it was encoded as that address rather than as
+    /// an offset
+    Target { raw: i32, address: Option<usize>, absolute: bool },
+}
+
+/// Code that did not decode to an instruction, such as an unknown opcode or a trailing partial
+/// instruction, which no assembly source can produce
+#[derive(Debug, PartialEq)]
+pub struct UndecodableInstruction {
+    pub address: usize,
+}
+
+impl fmt::Display for UndecodableInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the code at {:04x} is not a valid instruction", self.address)
+    }
+}
+
+impl Error for UndecodableInstruction {}
+
+/// One instruction decoded from bytecode
+#[derive(Debug, PartialEq, Clone)]
+pub struct DisassembledInstruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+}
+
+/// The instructions decoded from a program, plus the addresses that jumps target so they can be
+/// given labels
+#[derive(Debug, PartialEq)]
+pub struct Disassembly {
+    /// The image's read-only data section, if it had one
+    pub data: Vec<u8>,
+    pub instructions: Vec<DisassembledInstruction>,
+    labels: BTreeSet<usize>,
+    /// Names for labels taken from the source, instead of ones made up from the address
+    names: BTreeMap<usize, String>,
+    /// Whether absolute targets can be written as labels, which only holds when the code sits
+    /// where the assembler would put it
+    absolute_labels: bool,
+}
+
+impl Disassembly {
+    /// Disassembles a PIE image, skipping its header and keeping its data section as raw bytes
+    pub fn from_image(image: &[u8]) -> Result<Disassembly, HeaderError> {
+        pie::verify_header(image)?;
+        let code_start = pie::code_start(image);
+        let mut disassembly = Disassembly::from_raw(&image[code_start..], code_start);
+        disassembly.data = image[PIE_HEADER_LENGTH..code_start].to_vec();
+        disassembly.absolute_labels = true;
+        Ok(disassembly)
+    }
+
+    /// Disassembles a bare instruction stream that the VM would see starting at `base`. Unless
+    /// that is right after a header, absolute targets are left as numbers, since as labels they
+    /// would assemble to addresses after the header instead.
+    pub fn from_raw(code: &[u8], base: usize) -> Disassembly {
+        let absolute_labels = base == PIE_HEADER_LENGTH;
+        let instructions: Vec<DisassembledInstruction> = code
+            .chunks(INSTRUCTION_LENGTH)
+            .enumerate()
+            .map(|(i, bytes)| decode(bytes, base + i * INSTRUCTION_LENGTH))
+            .collect();
+        let starts: BTreeSet<usize> = instructions.iter().map(|i| i.address).collect();
+        let labels = instructions
+            .iter()
+            .flat_map(|i| i.operands.iter())
+            .filter_map(|o| match o {
+                Operand::Target { address: Some(address), absolute, .. }
+                    if starts.contains(address) && (absolute_labels || !absolute) =>
+                {
+                    Some(*address)
+                }
+                _ => None,
+            })
+            .collect();
+        Disassembly { data: vec![], instructions, labels, names: BTreeMap::new(), absolute_labels }
+    }
+
+    /// Uses the code labels from an assembler's symbol table instead of made up names
+    pub fn with_symbols(mut self, symbols: &SymbolTable) -> Disassembly {
+        for (name, address) in symbols.code_labels() {
+            self.labels.insert(address as usize);
+            self.names.insert(address as usize, name.to_string());
+        }
+        self
+    }
+
+    /// Lists up to `radius` instructions either side of `address`, marking the one at `address`
+    pub fn listing_around(&self, address: usize, radius: usize) -> String {
+        let index = self.instructions.iter().position(|i| i.address >= address).unwrap_or(self.instructions.len());
+        let start = index.saturating_sub(radius);
+        let end = (index + radius + 1).min(self.instructions.len());
+        let mut listing = String::new();
+        for instruction in &self.instructions[start..end] {
+            let marker = if instruction.address == address { "=>" } else { "  " };
+            listing.push_str(&format!("{} {:04x}  {}\n", marker, instruction.address, self.render(instruction)));
+        }
+        listing
+    }
+
+    /// The program as assembly text, which assembles back to the same bytes. Fails if any of the
+    /// code decoded as IGL, since the assembler has no way to write it.
+    pub fn to_source(&self) -> Result<String, UndecodableInstruction> {
+        if let Some(illegal) = self.instructions.iter().find(|i| i.opcode == Opcode::IGL) {
+            return Err(UndecodableInstruction { address: illegal.address });
+        }
+        let mut source = String::new();
+        if !self.data.is_empty() {
+            source.push_str(".data\n");
+            for bytes in self.data.chunks(3) {
+                source.push_str(".byte");
+                for byte in bytes {
+                    source.push_str(&format!(" #{}", byte));
+                }
+                source.push('\n');
+            }
+            source.push_str(".code\n");
+        }
+        for instruction in &self.instructions {
+            source.push_str(&self.render(instruction));
+            source.push('\n');
+        }
+        Ok(source)
+    }
+
+    fn render(&self, instruction: &DisassembledInstruction) -> String {
+        let mut text = String::new();
+        if self.labels.contains(&instruction.address) {
+            text.push_str(&format!("{}: ", self.label_name(instruction.address)));
+        }
+        text.push_str(instruction.opcode.mnemonic());
+        for operand in &instruction.operands {
+            text.push(' ');
+            text.push_str(&match operand {
+                Operand::Register(r) => format!("${}", r),
+                Operand::FloatRegister(r) => format!("$f{}", r),
+                Operand::Integer(v) => format!("#{}", v),
+                Operand::Target { address: Some(address), absolute, .. }
+                    if self.labels.contains(address) && (self.absolute_labels || !absolute) =>
+                {
+                    format!("@{}", self.label_name(*address))
+                }
+                Operand::Target { raw, .. } => format!("#{}", raw),
+            });
+        }
+        text
+    }
+
+    fn label_name(&self, address: usize) -> String {
+        match self.names.get(&address) {
+            Some(name) => name.clone(),
+            None => format!("L{:04x}", address),
+        }
+    }
+}
+
+/// Lists each instruction with its address
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.data.is_empty() {
+            writeln!(f, "{:04x}  ({} bytes of data)", PIE_HEADER_LENGTH, self.data.len())?;
+        }
+        for instruction in &self.instructions {
+            writeln!(f, "{:04x}  {}", instruction.address, self.render(instruction))?;
+        }
+        Ok(())
+    }
+}
+
+fn offset_target(end: usize, offset: i64) -> Option<usize> {
+    let target = end as i64 + offset;
+    if target < 0 {
+        None
+    } else {
+        Some(target as usize)
+    }
+}
+
+/// Decodes the instruction in `bytes`, found at `address`. Anything that is not a whole, well
+/// formed instruction with zeroed padding decodes as IGL.
+pub fn decode(bytes: &[u8], address: usize) -> DisassembledInstruction {
+    let illegal = DisassembledInstruction { address, opcode: Opcode::IGL, operands: vec![] };
+    if bytes.len() != INSTRUCTION_LENGTH {
+        return illegal;
+    }
+    let opcode = Opcode::from(bytes[0]);
+    let mut operands = vec![];
+    let mut i = 1;
+    for kind in opcode.operands() {
+        let word = match kind.size() {
+            1 => u16::from(bytes[i]),
+            _ => (u16::from(bytes[i]) << 8) | u16::from(bytes[i + 1]),
+        };
+        let end = address + INSTRUCTION_LENGTH;
+        operands.push(match kind {
+            OperandKind::Register => Operand::Register(word as u8),
+            OperandKind::FloatRegister => Operand::FloatRegister(word as u8),
+            OperandKind::Imm8 | OperandKind::Upper16 => Operand::Integer(i32::from(word)),
+            OperandKind::Imm16 => Operand::Integer(i32::from(word as i16)),
+            OperandKind::Address => {
+                Operand::Target { raw: i32::from(word), address: Some(word as usize), absolute: true }
+            }
+            OperandKind::ForwardOffset => {
+                Operand::Target { raw: i32::from(word), address: Some(end + word as usize), absolute: false }
+            }
+            OperandKind::BackwardOffset => {
+                Operand::Target { raw: i32::from(word), address: end.checked_sub(word as usize), absolute: false }
+            }
+            OperandKind::RelativeOffset => {
+                let raw = i32::from(word as i16);
+                Operand::Target { raw, address: offset_target(end, i64::from(raw)), absolute: false }
+            }
+            OperandKind::BranchOffset => {
+                let raw = i32::from(word as u8 as i8);
+                Operand::Target { raw, address: offset_target(end, i64::from(raw)), absolute: false }
+            }
+        });
+        i += kind.size();
+    }
+    if opcode == Opcode::IGL || bytes[i..].iter().any(|b| *b != 0) {
+        return illegal;
+    }
+    DisassembledInstruction { address, opcode, operands }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::Assembler;
+
+    #[test]
+    fn test_decode() {
+        let instruction = decode(&[0, 1, 0xff, 0xfe], 64);
+        assert_eq!(instruction.opcode, Opcode::LOAD);
+        assert_eq!(instruction.operands, vec![Operand::Register(1), Operand::Integer(-2)]);
+        assert_eq!(decode(&[5, 0, 0, 1], 64).opcode, Opcode::IGL);
+        assert_eq!(decode(&[5, 0], 64).opcode, Opcode::IGL);
+    }
+
+    #[test]
+    fn test_disassemble_raw() {
+        let code = [0, 0, 0, 10, 21, 0, 0, 0, 23, 0, 0, 0, 5, 0, 0, 0];
+        let disassembly = Disassembly::from_raw(&code, 0);
+        assert_eq!(disassembly.to_source(), Ok("load $0 #10\njmp #0\njmpf @L000c\nL000c: hlt\n".to_string()));
+        assert_eq!(
+            disassembly.to_string(),
+            "0000  load $0 #10\n0004  jmp #0\n0008  jmpf @L000c\n000c  L000c: hlt\n"
+        );
+        let image = Assembler::new().assemble(&disassembly.to_source().unwrap()).unwrap();
+        assert_eq!(&image[PIE_HEADER_LENGTH..], &code);
+
+        // Right after a header an absolute jump can keep its label
+        let code = [0, 0, 0, 10, 21, 0, 64, 0, 5, 0, 0, 0];
+        let disassembly = Disassembly::from_raw(&code, PIE_HEADER_LENGTH);
+        assert_eq!(disassembly.to_source(), Ok("L0040: load $0 #10\njmp @L0040\nhlt\n".to_string()));
+        let image = Assembler::new().assemble(&disassembly.to_source().unwrap()).unwrap();
+        assert_eq!(&image[PIE_HEADER_LENGTH..], &code);
+    }
+
+    #[test]
+    fn test_listing_with_symbols() {
+        let mut asm = Assembler::new();
+        let image = asm.assemble("load $0 #1\nloop: inc $0\njmp @loop\nhlt").unwrap();
+        let disassembly = Disassembly::from_image(&image).unwrap().with_symbols(&asm.symbols);
+        assert_eq!(
+            disassembly.listing_around(68, 1),
+            "   0040  load $0 #1\n=> 0044  loop: inc $0\n   0048  jmp @loop\n"
+        );
+        assert_eq!(disassembly.listing_around(76, 1), "   0048  jmp @loop\n=> 004c  hlt\n");
+    }
+
+    #[test]
+    fn test_disassembly_reassembles() {
+        let source = ".data\ntext: .asciiz 'abcd'\n.code\nload $0 #-5\nload $1 @text\nback: inc $0\nneq $0 $1\njmpf @check\nhlt\ncheck: jmpe @back\njmpne @back\nbf @check\nblt $0 $1 @back\nbge $1 $0 #-1\ncall @sub\njmpb @back\nsub: storew $0 $1 #4\naddf $f0 $f1 $f2\nret";
+        let mut asm = Assembler::new();
+        let image = asm.assemble(source).unwrap();
+        let disassembly = Disassembly::from_image(&image).unwrap();
+        assert_eq!(asm.assemble(&disassembly.to_source().unwrap()), Ok(image));
+    }
+
+    #[test]
+    fn test_undecodable_code_has_no_source() {
+        let mut asm = Assembler::new();
+        let mut image = asm.assemble("load $0 #1\nhlt").unwrap();
+        image.push(7);
+        let disassembly = Disassembly::from_image(&image).unwrap();
+        assert_eq!(disassembly.to_source(), Err(UndecodableInstruction { address: 72 }));
+        // The listing still shows it
+        assert_eq!(disassembly.to_string(), "0040  load $0 #1\n0044  hlt\n0048  igl\n");
+
+        let disassembly = Disassembly::from_raw(&[5, 0, 0, 0, 20, 0, 0, 0], 0);
+        assert_eq!(disassembly.to_source(), Err(UndecodableInstruction { address: 4 }));
+    }
+}
@@ -22,10 +22,118 @@ pub enum Opcode{
     NOP,
     ALOC, //for allocating memory to the heap
     INC,
-    DEC
+    DEC,
+    JMPI,//jump to an absolute address, e.g. a label
+    JEQI,//jump to an absolute address if equal
+    JMPFI,//jump forward by an immediate offset
+    LOADB,//load a byte from the heap
+    LOADW,//load a 4 byte word from the heap
+    STOREB,//store a byte to the heap
+    STOREW,//store a 4 byte word to the heap
+    CALL,//call the subroutine at the address in a register
+    CALLI,//call the subroutine at an absolute address, e.g. a label
+    RET,//return from a subroutine
+    PUSH,//push a register onto the stack
+    POP,//pop the top of the stack into a register
+    JMPBI,//jump backward by an immediate offset
+    AND,//bitwise and
+    OR,//bitwise or
+    XOR,//bitwise exclusive or
+    NOT,//bitwise complement of one register into another
+    SHL,//shift left
+    SHR,//logical shift right, filling with zeroes
+    SAR,//arithmetic shift right, filling with the sign bit
+    MOD,//remainder of a division
+    MFR,//move the remainder left by the last DIV into a register
+    ADDV,//add, faulting on signed overflow
+    SUBV,//subtract, faulting on signed overflow
+    MULV,//multiply, faulting on signed overflow
+    MOV,//copy one register into another
+    LUI,//load an immediate into the upper 16 bits of a register, keeping the lower 16
+    LOADF,//load a float from the heap
+    STOREF,//store a float to the heap
+    ADDF,//add floats
+    SUBF,//subtract floats
+    MULF,//multiply floats
+    DIVF,//divide floats
+    EQF,//float equal
+    NEQF,//float not equal
+    GTF,//float greater than
+    LTF,//float less than
+    GTEF,//float greater than or equal to
+    LTEF,//float less than or equal to
+    ITOF,//convert an integer register to a float register
+    FTOI,//convert a float register to an integer register, truncating
+    JNEQ,//jump to the address in a register if not equal
+    JNEQI,//jump to an absolute address if not equal
+    BT,//branch by a relative offset if the equal flag is set
+    BF,//branch by a relative offset if the equal flag is clear
+    BEQ,//compare two registers and branch if equal
+    BNE,//compare two registers and branch if not equal
+    BLT,//compare two registers and branch if less than
+    BGT,//compare two registers and branch if greater than
+    BLE,//compare two registers and branch if less than or equal to
+    BGE//compare two registers and branch if greater than or equal to
+}
 
+/// What an operand is and how it is laid out in the 3 bytes after the opcode
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OperandKind {
+    /// One byte register number
+    Register,
+    /// One byte float register number
+    FloatRegister,
+    /// 16-bit immediate, sign-extended by the VM
+    Imm16,
+    /// 16 bits for the upper half of a register, written signed or unsigned
+    Upper16,
+    /// One byte unsigned immediate
+    Imm8,
+    /// 16-bit absolute address, usually written as a label
+    Address,
+    /// 16-bit distance forward from the end of the instruction
+    ForwardOffset,
+    /// 16-bit distance backward from the end of the instruction
+    BackwardOffset,
+    /// Signed 16-bit distance from the end of the instruction
+    RelativeOffset,
+    /// Signed 8-bit distance in bytes from the end of the instruction
+    BranchOffset,
 }
 
+impl OperandKind {
+    pub fn size(self) -> usize {
+        match self {
+            OperandKind::Register
+            | OperandKind::FloatRegister
+            | OperandKind::Imm8
+            | OperandKind::BranchOffset => 1,
+            _ => 2,
+        }
+    }
+
+    /// What the assembler accepts for this operand, for error messages
+    pub fn description(self) -> &'static str {
+        match self {
+            OperandKind::Register => "a register",
+            OperandKind::FloatRegister => "a float register",
+            OperandKind::Imm16 => "an integer or label",
+            OperandKind::Upper16 | OperandKind::Imm8 => "an integer",
+            OperandKind::Address
+            | OperandKind::ForwardOffset
+            | OperandKind::BackwardOffset
+            | OperandKind::RelativeOffset
+            | OperandKind::BranchOffset => "a label or integer",
+        }
+    }
+}
+
+/// Every instruction is this many bytes long; unused bytes after the operands are padding
+pub const INSTRUCTION_LENGTH: usize = 4;
+
+/// How many registers, and how many float registers, the VM has
+pub const REGISTER_COUNT: usize = 32;
+
 #[derive(Debug, PartialEq)]
 pub struct Instruction{
     opcode:Opcode
@@ -34,7 +142,7 @@ pub struct Instruction{
 impl Instruction{
     pub fn new(opcode:Opcode)->Instruction{
         Instruction{
-            opcode:opcode
+            opcode
         }
     }
 }
@@ -63,6 +171,57 @@ impl From<u8> for Opcode {
             17=>Opcode::ALOC,
             18=>Opcode::INC,
             19=>Opcode::DEC,
+            21=>Opcode::JMPI,
+            22=>Opcode::JEQI,
+            23=>Opcode::JMPFI,
+            24=>Opcode::LOADB,
+            25=>Opcode::LOADW,
+            26=>Opcode::STOREB,
+            27=>Opcode::STOREW,
+            28=>Opcode::CALL,
+            29=>Opcode::CALLI,
+            30=>Opcode::RET,
+            31=>Opcode::PUSH,
+            32=>Opcode::POP,
+            33=>Opcode::JMPBI,
+            34=>Opcode::AND,
+            35=>Opcode::OR,
+            36=>Opcode::XOR,
+            37=>Opcode::NOT,
+            38=>Opcode::SHL,
+            39=>Opcode::SHR,
+            40=>Opcode::SAR,
+            41=>Opcode::MOD,
+            42=>Opcode::MFR,
+            43=>Opcode::ADDV,
+            44=>Opcode::SUBV,
+            45=>Opcode::MULV,
+            46=>Opcode::MOV,
+            47=>Opcode::LUI,
+            48=>Opcode::LOADF,
+            49=>Opcode::STOREF,
+            50=>Opcode::ADDF,
+            51=>Opcode::SUBF,
+            52=>Opcode::MULF,
+            53=>Opcode::DIVF,
+            54=>Opcode::EQF,
+            55=>Opcode::NEQF,
+            56=>Opcode::GTF,
+            57=>Opcode::LTF,
+            58=>Opcode::GTEF,
+            59=>Opcode::LTEF,
+            60=>Opcode::ITOF,
+            61=>Opcode::FTOI,
+            62=>Opcode::JNEQ,
+            63=>Opcode::JNEQI,
+            64=>Opcode::BT,
+            65=>Opcode::BF,
+            66=>Opcode::BEQ,
+            67=>Opcode::BNE,
+            68=>Opcode::BLT,
+            69=>Opcode::BGT,
+            70=>Opcode::BLE,
+            71=>Opcode::BGE,
             _ => Opcode::IGL
         }
     }
@@ -91,7 +250,58 @@ impl From<Opcode> for u8 {
             Opcode::ALOC => 17,
             Opcode::INC => 18,
             Opcode::DEC => 19,
-            Opcode::IGL=>20
+            Opcode::IGL=>20,
+            Opcode::JMPI => 21,
+            Opcode::JEQI => 22,
+            Opcode::JMPFI => 23,
+            Opcode::LOADB => 24,
+            Opcode::LOADW => 25,
+            Opcode::STOREB => 26,
+            Opcode::STOREW => 27,
+            Opcode::CALL => 28,
+            Opcode::CALLI => 29,
+            Opcode::RET => 30,
+            Opcode::PUSH => 31,
+            Opcode::POP => 32,
+            Opcode::JMPBI => 33,
+            Opcode::AND => 34,
+            Opcode::OR => 35,
+            Opcode::XOR => 36,
+            Opcode::NOT => 37,
+            Opcode::SHL => 38,
+            Opcode::SHR => 39,
+            Opcode::SAR => 40,
+            Opcode::MOD => 41,
+            Opcode::MFR => 42,
+            Opcode::ADDV => 43,
+            Opcode::SUBV => 44,
+            Opcode::MULV => 45,
+            Opcode::MOV => 46,
+            Opcode::LUI => 47,
+            Opcode::LOADF => 48,
+            Opcode::STOREF => 49,
+            Opcode::ADDF => 50,
+            Opcode::SUBF => 51,
+            Opcode::MULF => 52,
+            Opcode::DIVF => 53,
+            Opcode::EQF => 54,
+            Opcode::NEQF => 55,
+            Opcode::GTF => 56,
+            Opcode::LTF => 57,
+            Opcode::GTEF => 58,
+            Opcode::LTEF => 59,
+            Opcode::ITOF => 60,
+            Opcode::FTOI => 61,
+            Opcode::JNEQ => 62,
+            Opcode::JNEQI => 63,
+            Opcode::BT => 64,
+            Opcode::BF => 65,
+            Opcode::BEQ => 66,
+            Opcode::BNE => 67,
+            Opcode::BLT => 68,
+            Opcode::BGT => 69,
+            Opcode::BLE => 70,
+            Opcode::BGE => 71
         }
     }
 }
@@ -119,11 +329,189 @@ impl<'a> From<CompleteStr<'a>> for Opcode {
             CompleteStr("aloc")=>Opcode::ALOC,
             CompleteStr("inc")=>Opcode::INC,
             CompleteStr("dec")=>Opcode::DEC,
+            CompleteStr("loadb")=>Opcode::LOADB,
+            CompleteStr("loadw")=>Opcode::LOADW,
+            CompleteStr("storeb")=>Opcode::STOREB,
+            CompleteStr("storew")=>Opcode::STOREW,
+            CompleteStr("call")=>Opcode::CALL,
+            CompleteStr("ret")=>Opcode::RET,
+            CompleteStr("push")=>Opcode::PUSH,
+            CompleteStr("pop")=>Opcode::POP,
+            CompleteStr("and")=>Opcode::AND,
+            CompleteStr("or")=>Opcode::OR,
+            CompleteStr("xor")=>Opcode::XOR,
+            CompleteStr("not")=>Opcode::NOT,
+            CompleteStr("shl")=>Opcode::SHL,
+            CompleteStr("shr")=>Opcode::SHR,
+            CompleteStr("sar")=>Opcode::SAR,
+            CompleteStr("mod")=>Opcode::MOD,
+            CompleteStr("mfr")=>Opcode::MFR,
+            CompleteStr("addv")=>Opcode::ADDV,
+            CompleteStr("subv")=>Opcode::SUBV,
+            CompleteStr("mulv")=>Opcode::MULV,
+            CompleteStr("mov")=>Opcode::MOV,
+            CompleteStr("lui")=>Opcode::LUI,
+            CompleteStr("loadf")=>Opcode::LOADF,
+            CompleteStr("storef")=>Opcode::STOREF,
+            CompleteStr("addf")=>Opcode::ADDF,
+            CompleteStr("subf")=>Opcode::SUBF,
+            CompleteStr("mulf")=>Opcode::MULF,
+            CompleteStr("divf")=>Opcode::DIVF,
+            CompleteStr("eqf")=>Opcode::EQF,
+            CompleteStr("neqf")=>Opcode::NEQF,
+            CompleteStr("gtf")=>Opcode::GTF,
+            CompleteStr("ltf")=>Opcode::LTF,
+            CompleteStr("gtef")=>Opcode::GTEF,
+            CompleteStr("ltef")=>Opcode::LTEF,
+            CompleteStr("itof")=>Opcode::ITOF,
+            CompleteStr("ftoi")=>Opcode::FTOI,
+            CompleteStr("jmpne")=>Opcode::JNEQ,
+            CompleteStr("bt")=>Opcode::BT,
+            CompleteStr("bf")=>Opcode::BF,
+            CompleteStr("beq")=>Opcode::BEQ,
+            CompleteStr("bne")=>Opcode::BNE,
+            CompleteStr("blt")=>Opcode::BLT,
+            CompleteStr("bgt")=>Opcode::BGT,
+            CompleteStr("ble")=>Opcode::BLE,
+            CompleteStr("bge")=>Opcode::BGE,
             _ => Opcode::IGL,
         }
     }
 }
 
+impl Opcode {
+    /// The assembly mnemonic for this opcode, the inverse of the `CompleteStr` conversion. Jumps
+    /// to an immediate share the mnemonic of their register form.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::LOAD => "load",
+            Opcode::ADD => "add",
+            Opcode::SUB => "sub",
+            Opcode::MUL => "mul",
+            Opcode::DIV => "div",
+            Opcode::HLT => "hlt",
+            Opcode::JMP | Opcode::JMPI => "jmp",
+            Opcode::JMPF | Opcode::JMPFI => "jmpf",
+            Opcode::JMPB | Opcode::JMPBI => "jmpb",
+            Opcode::EQ => "eq",
+            Opcode::NEQ => "neq",
+            Opcode::GTQ => "gte",
+            Opcode::GT => "gt",
+            Opcode::LTQ => "lte",
+            Opcode::LT => "lt",
+            Opcode::JEQ | Opcode::JEQI => "jmpe",
+            Opcode::NOP => "nop",
+            Opcode::ALOC => "aloc",
+            Opcode::INC => "inc",
+            Opcode::DEC => "dec",
+            Opcode::LOADB => "loadb",
+            Opcode::LOADW => "loadw",
+            Opcode::STOREB => "storeb",
+            Opcode::STOREW => "storew",
+            Opcode::CALL | Opcode::CALLI => "call",
+            Opcode::RET => "ret",
+            Opcode::PUSH => "push",
+            Opcode::POP => "pop",
+            Opcode::AND => "and",
+            Opcode::OR => "or",
+            Opcode::XOR => "xor",
+            Opcode::NOT => "not",
+            Opcode::SHL => "shl",
+            Opcode::SHR => "shr",
+            Opcode::SAR => "sar",
+            Opcode::MOD => "mod",
+            Opcode::MFR => "mfr",
+            Opcode::ADDV => "addv",
+            Opcode::SUBV => "subv",
+            Opcode::MULV => "mulv",
+            Opcode::MOV => "mov",
+            Opcode::LUI => "lui",
+            Opcode::LOADF => "loadf",
+            Opcode::STOREF => "storef",
+            Opcode::ADDF => "addf",
+            Opcode::SUBF => "subf",
+            Opcode::MULF => "mulf",
+            Opcode::DIVF => "divf",
+            Opcode::EQF => "eqf",
+            Opcode::NEQF => "neqf",
+            Opcode::GTF => "gtf",
+            Opcode::LTF => "ltf",
+            Opcode::GTEF => "gtef",
+            Opcode::LTEF => "ltef",
+            Opcode::ITOF => "itof",
+            Opcode::FTOI => "ftoi",
+            Opcode::JNEQ | Opcode::JNEQI => "jmpne",
+            Opcode::BT => "bt",
+            Opcode::BF => "bf",
+            Opcode::BEQ => "beq",
+            Opcode::BNE => "bne",
+            Opcode::BLT => "blt",
+            Opcode::BGT => "bgt",
+            Opcode::BLE => "ble",
+            Opcode::BGE => "bge",
+            Opcode::IGL => "igl",
+        }
+    }
+
+    /// The operands this opcode takes, in order
+    pub fn operands(self) -> &'static [OperandKind] {
+        use self::OperandKind::*;
+        match self {
+            Opcode::LOAD => &[Register, Imm16],
+            Opcode::LUI => &[Register, Upper16],
+            Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV | Opcode::MOD => {
+                &[Register, Register, Register]
+            }
+            Opcode::ADDV | Opcode::SUBV | Opcode::MULV => &[Register, Register, Register],
+            Opcode::AND | Opcode::OR | Opcode::XOR | Opcode::SHL | Opcode::SHR | Opcode::SAR => {
+                &[Register, Register, Register]
+            }
+            Opcode::NOT | Opcode::MOV => &[Register, Register],
+            Opcode::EQ | Opcode::NEQ | Opcode::GT | Opcode::LT | Opcode::GTQ | Opcode::LTQ => {
+                &[Register, Register]
+            }
+            Opcode::JMP | Opcode::JMPF | Opcode::JMPB | Opcode::JEQ | Opcode::CALL => &[Register],
+            Opcode::ALOC | Opcode::INC | Opcode::DEC | Opcode::PUSH | Opcode::POP => &[Register],
+            Opcode::MFR => &[Register],
+            Opcode::JNEQ => &[Register],
+            Opcode::JMPI | Opcode::JEQI | Opcode::JNEQI | Opcode::CALLI => &[Address],
+            Opcode::BT | Opcode::BF => &[RelativeOffset],
+            Opcode::BEQ | Opcode::BNE | Opcode::BLT | Opcode::BGT | Opcode::BLE | Opcode::BGE => {
+                &[Register, Register, BranchOffset]
+            }
+            Opcode::JMPFI => &[ForwardOffset],
+            Opcode::JMPBI => &[BackwardOffset],
+            Opcode::LOADB | Opcode::LOADW | Opcode::STOREB | Opcode::STOREW => {
+                &[Register, Register, Imm8]
+            }
+            Opcode::LOADF | Opcode::STOREF => &[FloatRegister, Register, Imm8],
+            Opcode::ADDF | Opcode::SUBF | Opcode::MULF | Opcode::DIVF => {
+                &[FloatRegister, FloatRegister, FloatRegister]
+            }
+            Opcode::EQF | Opcode::NEQF | Opcode::GTF | Opcode::LTF | Opcode::GTEF | Opcode::LTEF => {
+                &[FloatRegister, FloatRegister]
+            }
+            Opcode::ITOF => &[Register, FloatRegister],
+            Opcode::FTOI => &[FloatRegister, Register],
+            Opcode::HLT | Opcode::NOP | Opcode::RET | Opcode::IGL => &[],
+        }
+    }
+
+    /// The variant of a jump or call opcode that takes an address or offset immediate instead of a
+    /// register, used when the assembler finds a label as the jump target
+    pub fn with_label_target(self) -> Option<Opcode> {
+        match self {
+            Opcode::JMP => Some(Opcode::JMPI),
+            Opcode::JEQ => Some(Opcode::JEQI),
+            Opcode::JNEQ => Some(Opcode::JNEQI),
+            Opcode::JMPF => Some(Opcode::JMPFI),
+            Opcode::JMPB => Some(Opcode::JMPBI),
+            Opcode::CALL => Some(Opcode::CALLI),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2,6 +2,8 @@ pub mod vm;
 pub mod instruction;
 pub mod repl;
 pub mod assembler;
+pub mod pie;
+pub mod disassembler;
 
 #[macro_use]
 extern crate nom;
@@ -9,7 +11,7 @@ extern crate nom;
 #[macro_use]
 extern crate clap;
 
-use clap::{Arg, App, SubCommand};
+use clap::App;
 use std::{path::Path, fs::File, io::Read};
 
 
@@ -28,12 +30,28 @@ fn main() {
             let mut vm = vm::VM::new();
             let program = asm.assemble(&program);
             match program {
-                Some(p) => {
-                    vm.add_bytes(p);
-                    vm.run();
-                    std::process::exit(0);
+                Ok(p) => {
+                    if let Err(e) = vm.load_program(p) {
+                        println!("Unable to load program: {}", e);
+                        std::process::exit(1);
+                    }
+                    match vm.run() {
+                        Ok(reason) => {
+                            println!("{}", reason);
+                            std::process::exit(0);
+                        },
+                        Err(fault) => {
+                            println!("VM fault: {}", fault);
+                            std::process::exit(1);
+                        }
+                    }
                 },
-                None => {}
+                Err(errors) => {
+                    for error in errors {
+                        println!("{}: {}", filename, error);
+                    }
+                    std::process::exit(1);
+                }
             }
         },
         None => {
@@ -44,7 +62,7 @@ fn main() {
 //start a repl that would run
 fn start_repl() {
     let mut repl = repl::REPL::new();
-    repl.run();
+    std::process::exit(repl.run());
 }
 
 //read file
@@ -55,7 +73,7 @@ fn read_file(tmp: &str) -> String {
         let mut contents = String::new();
         match fh.read_to_string(&mut contents) {
           Ok(_) => {
-            return contents;
+            contents
           },
           Err(e) => {
             println!("There was an error reading file: {:?}", e);
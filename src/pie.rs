@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::fmt;
+
+/// Magic bytes every PIE image starts with
+pub const PIE_HEADER_PREFIX: [u8; 4] = [45, 50, 49, 45];
+/// Length of the whole header, prefix included. The read-only data section starts right after
+/// it, followed by the code.
+pub const PIE_HEADER_LENGTH: usize = 64;
+/// Where the header stores the length of the data section, as a big-endian u32
+const DATA_LENGTH_OFFSET: usize = 4;
+
+/// Why a byte image could not be loaded as a PIE program
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HeaderError {
+    /// The image is shorter than a header
+    TooShort { length: usize },
+    /// The image does not start with `PIE_HEADER_PREFIX`
+    BadPrefix,
+    /// The header claims a data section longer than the rest of the image
+    DataTooLong { length: usize },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::TooShort { length } => write!(
+                f,
+                "image is {} bytes long, too short for the {} byte PIE header",
+                length, PIE_HEADER_LENGTH
+            ),
+            HeaderError::BadPrefix => write!(f, "image does not start with the PIE magic bytes"),
+            HeaderError::DataTooLong { length } => {
+                write!(f, "data section of {} bytes runs past the end of the image", length)
+            }
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+/// Writes out the header: the 4 magic bytes and the length of the data section, padded with 0s
+/// so we can use those bytes later if needed
+pub fn write_header(data_length: usize) -> Vec<u8> {
+    let mut header = PIE_HEADER_PREFIX.to_vec();
+    header.extend_from_slice(&(data_length as u32).to_be_bytes());
+    header.resize(PIE_HEADER_LENGTH, 0);
+    header
+}
+
+/// Turns bare code into an image with no data section
+pub fn prepend_header(mut b: Vec<u8>) -> Vec<u8> {
+    let mut image = write_header(0);
+    image.append(&mut b);
+    image
+}
+
+/// The length of the data section recorded in a header that has been verified
+pub fn data_length(image: &[u8]) -> usize {
+    let mut length = [0; 4];
+    length.copy_from_slice(&image[DATA_LENGTH_OFFSET..DATA_LENGTH_OFFSET + 4]);
+    u32::from_be_bytes(length) as usize
+}
+
+/// Where the code starts in a verified image
+pub fn code_start(image: &[u8]) -> usize {
+    PIE_HEADER_LENGTH + data_length(image)
+}
+
+/// Checks that `image` starts with a well-formed header
+pub fn verify_header(image: &[u8]) -> Result<(), HeaderError> {
+    if image.len() < PIE_HEADER_LENGTH {
+        return Err(HeaderError::TooShort { length: image.len() });
+    }
+    if image[0..4] != PIE_HEADER_PREFIX {
+        return Err(HeaderError::BadPrefix);
+    }
+    let length = data_length(image);
+    if PIE_HEADER_LENGTH + length > image.len() {
+        return Err(HeaderError::DataTooLong { length });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_header() {
+        let header = write_header(0);
+        assert_eq!(header.len(), PIE_HEADER_LENGTH);
+        assert_eq!(verify_header(&header), Ok(()));
+        let mut image = write_header(3);
+        assert_eq!(verify_header(&image), Err(HeaderError::DataTooLong { length: 3 }));
+        image.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(verify_header(&image), Ok(()));
+        assert_eq!(code_start(&image), PIE_HEADER_LENGTH + 3);
+    }
+
+    #[test]
+    fn test_verify_header() {
+        assert_eq!(verify_header(&[45, 50, 49, 45]), Err(HeaderError::TooShort { length: 4 }));
+        assert_eq!(verify_header(&[0; PIE_HEADER_LENGTH]), Err(HeaderError::BadPrefix));
+        assert_eq!(verify_header(&prepend_header(vec![5, 0, 0, 0])), Ok(()));
+    }
+}
@@ -0,0 +1,106 @@
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::instruction::Opcode;
+
+/// The REPL's own commands, which all start with a dot
+pub const COMMANDS: &[&str] = &[
+    ".quit",
+    ".history",
+    ".registers",
+    ".heap",
+    ".disasm",
+    ".pc",
+    ".break",
+    ".delete",
+    ".step",
+    ".continue",
+    ".run",
+    ".reset",
+    ".clear",
+    ".load_file",
+];
+
+/// Completes REPL commands, opcode mnemonics, register names and the labels of the last file
+/// that was loaded
+pub struct ReplHelper {
+    mnemonics: Vec<&'static str>,
+    pub labels: Vec<String>,
+}
+
+impl Default for ReplHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplHelper {
+    pub fn new() -> ReplHelper {
+        let mut mnemonics: Vec<&'static str> = (0..=u8::MAX)
+            .map(Opcode::from)
+            .filter(|opcode| *opcode != Opcode::IGL)
+            .map(Opcode::mnemonic)
+            .collect();
+        // `li` expands to other instructions, so it has no opcode of its own
+        mnemonics.push("li");
+        mnemonics.sort_unstable();
+        mnemonics.dedup();
+        ReplHelper { mnemonics, labels: vec![] }
+    }
+
+    /// Everything `word` could be completed to, chosen by how it starts
+    pub fn candidates(&self, word: &str) -> Vec<String> {
+        let options: Vec<String> = if word.starts_with('.') {
+            COMMANDS.iter().map(|command| command.to_string()).collect()
+        } else if word.starts_with("$f") {
+            (0..32).map(|r| format!("$f{}", r)).collect()
+        } else if word.starts_with('$') {
+            (0..32).map(|r| format!("${}", r)).chain((0..32).map(|r| format!("$f{}", r))).collect()
+        } else if word.starts_with('@') {
+            self.labels.iter().map(|label| format!("@{}", label)).collect()
+        } else {
+            self.mnemonics.iter().map(|mnemonic| mnemonic.to_string()).collect()
+        };
+        options.into_iter().filter(|option| option.starts_with(word)).collect()
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        Ok((start, self.candidates(&line[start..pos])))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mut helper = ReplHelper::new();
+        helper.labels = vec!["loop".to_string(), "end".to_string()];
+        assert_eq!(helper.candidates(".reg"), vec![".registers"]);
+        assert_eq!(helper.candidates(".r"), vec![".registers", ".run", ".reset"]);
+        assert_eq!(helper.candidates("jmp"), vec!["jmp", "jmpb", "jmpe", "jmpf", "jmpne"]);
+        assert!(helper.candidates("l").contains(&"li".to_string()));
+        assert_eq!(helper.candidates("$3"), vec!["$3", "$30", "$31"]);
+        assert_eq!(helper.candidates("$f1").len(), 11);
+        assert_eq!(helper.candidates("@l"), vec!["@loop"]);
+    }
+}
@@ -1,108 +1,463 @@
+pub mod completer;
+
 use std;
-use std::io;
-use std::io::Write;
 //import the vm
 use crate::vm::VM;
-use nom::types::CompleteStr;
-use crate::assembler::program_parser::program;
-use std::path::Path;
+use crate::assembler::program_parser::parse_program;
+use crate::assembler::{Assembler, SymbolTable, SymbolType};
+use crate::disassembler::Disassembly;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::Read;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use std::fmt;
+use self::completer::ReplHelper;
+
+// Where command history is kept between sessions, in the user's home directory
+const HISTORY_FILE: &str = ".vm_history";
 
 //core structure of the repl for the assembler
 pub struct REPL{
-    command_buffer:Vec<String>,//vec of executed programs
-    vm:VM
+    // Reads lines with editing, history and tab completion
+    editor:Editor<ReplHelper>,
+    vm:VM,
+    // Assembles loaded files and keeps their labels, so breakpoints can be set by name
+    assembler:Assembler,
+    // The registers before the last instruction ran, so `.registers` can point out changes
+    previous_registers:[i32; 32],
+    // Whether any command has failed, which makes `run` return a non-zero exit status
+    failed:bool
+}
+
+impl Default for REPL {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl REPL{
     pub fn new()->REPL{
+        let mut editor = Editor::new();
+        editor.set_helper(Some(ReplHelper::new()));
         REPL{
+            editor,
             vm:VM::new(),
-            command_buffer:vec![]
+            assembler:Assembler::new(),
+            previous_registers:[0; 32],
+            failed:false
         }
     }
 
-    pub fn run(&mut self) {
+    // Reads and runs commands until `.quit` or the end of input, returning the exit status: 1 if
+    // any command failed, so scripts piped into the REPL can be checked
+    pub fn run(&mut self) -> i32 {
         println!("Welcome to my vm! Let's be productive!");
+        let history = history_path();
+        if let Some(path) = &history {
+            // There is no history yet the first time the REPL runs
+            let _ = self.editor.load_history(path);
+        }
         loop {
-            // This allocates a new String in which to store whatever the user types each iteration.
-            // TODO: Figure out how create this outside of the loop and re-use it every iteration
-            let mut buffer = String::new();
-    
-            // Blocking call until the user types in a command
-            let stdin = io::stdin();
-    
-            // Annoyingly, `print!` does not automatically flush stdout like `println!` does, so we
-            // have to do that there for the user to see our `>>> ` prompt.
-            print!(">>> ");
-            io::stdout().flush().expect("Unable to flush stdout");
-    
-            // Here we'll look at the string the user gave us.
-            stdin.read_line(&mut buffer).expect("Unable to read line from user");
+            // Blocking call until the user types in a command. The end of input quits, and Ctrl-C
+            // just abandons the line being typed.
+            let buffer = match self.editor.readline(">>> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    self.fail(format!("Unable to read input: {}", e));
+                    break;
+                }
+            };
             let buffer = buffer.trim();
 
             // This is the line we add to store a copy of each command history
-            self.command_buffer.push(buffer.to_string());
-            match buffer {
-                ".quit" => {
-                    println!("Farewell! Have a great day!");
-                    std::process::exit(0);
-                },
-                ".history" => {
-                    for command in &self.command_buffer {
+            if !buffer.is_empty() {
+                self.editor.add_history_entry(buffer);
+                if let Some(path) = &history {
+                    if let Err(e) = self.editor.save_history(path) {
+                        println!("Unable to save history to {}: {}", path.display(), e);
+                    }
+                }
+            }
+            let command: Vec<&str> = buffer.split_whitespace().collect();
+            match command.as_slice() {
+                [] => {},
+                [".quit"] => break,
+                [".history"] => {
+                    for command in self.editor.history().iter() {
                         println!("{}", command);
                     }
                 },
-                ".registers"=>{
-                    println!("listing all programs in memory");
-                    println!("{:?}", self.vm.program);
+                [".registers"]=>{
+                    self.print_registers();
+                },
+                [".heap", offset, length]=>{
+                    match (parse_number(offset), parse_number(length)) {
+                        (Some(offset), Some(length)) => {
+                            let heap = self.vm.heap();
+                            let end = offset.saturating_add(length).min(heap.len());
+                            if offset >= end {
+                                println!("nothing to show, the heap is {} bytes", heap.len());
+                            } else {
+                                print!("{}", hexdump(&heap[offset..end], offset));
+                            }
+                        },
+                        _ => self.fail("usage: .heap <offset> <length>"),
+                    }
+                },
+                // Shows the instructions around the pc, or with `all` the whole program
+                [".disasm"]=>{
+                    print!("{}", self.disassembly().listing_around(self.vm.pc(), 5));
+                },
+                [".disasm", "all"]=>{
+                    print!("{}", self.disassembly());
+                },
+                [".pc"]=>{
+                    print!("{}", self.disassembly().listing_around(self.vm.pc(), 0));
+                },
+                [".break"]=>{
+                    for address in self.vm.breakpoints() {
+                        match self.assembler.symbols.label_at(*address as u32) {
+                            Some(name) => println!("{:04x}  {}", address, name),
+                            None => println!("{:04x}", address),
+                        }
+                    }
+                },
+                [".break", target]=>{
+                    match self.resolve_address(target) {
+                        Ok(address) => {
+                            self.vm.add_breakpoint(address);
+                            println!("breakpoint at {:04x}", address);
+                        },
+                        Err(e) => self.fail(e),
+                    }
+                },
+                [".delete", target]=>{
+                    match self.resolve_address(target) {
+                        Ok(address) if self.vm.remove_breakpoint(address) => {
+                            println!("removed breakpoint at {:04x}", address);
+                        },
+                        Ok(address) => self.fail(format!("no breakpoint at {:04x}", address)),
+                        Err(e) => self.fail(e),
+                    }
+                },
+                [".step"]=>{
+                    self.step(1);
                 },
-                ".clear"=>{
+                [".step", count]=>{
+                    match count.parse::<usize>() {
+                        Ok(count) => self.step(count),
+                        Err(_) => self.fail(format!("expected a number of instructions to step, found `{}`", count)),
+                    }
+                },
+                [".continue"]=>{
+                    self.previous_registers = self.vm.registers;
+                    match self.vm.resume() {
+                        Ok(reason) => println!("{}", reason),
+                        Err(fault) => self.fail(format!("VM fault: {}", fault)),
+                    }
+                    if self.vm.pc() < self.vm.program.len() {
+                        print!("{}", self.disassembly().listing_around(self.vm.pc(), 0));
+                    }
+                },
+                [".clear"]=>{
                     //to clear the program in memory
                     println!("clearing all program from memory");
                     self.vm.clear_program();
                 },
-                ".load_file" => {
-                    print!("Please enter the path to the file you wish to load: ");
-                    io::stdout().flush().expect("Unable to flush stdout");
-                    let mut tmp = String::new();
-                    stdin.read_line(&mut tmp).expect("Unable to read line from user");
-                    let tmp = tmp.trim();
-                    let filename = Path::new(&tmp);
-                    let mut f = File::open(Path::new(&filename)).expect("File not found");
-                    let mut contents = String::new();
-                    f.read_to_string(&mut contents).expect("There was an error reading from the file");
-                    let program = match program(CompleteStr(&contents)) {
-                        // Rusts pattern matching is pretty powerful an can even be nested
-                        Ok((remainder, program)) => {
-                            program
-                        },
+                [".load_file"] => {
+                    let tmp = match self.editor.readline("Please enter the path to the file you wish to load: ") {
+                        Ok(tmp) => tmp,
                         Err(e) => {
-                            println!("Unable to parse input: {:?}", e);
+                            self.fail(format!("No file to load: {}", e));
                             continue;
                         }
                     };
-                    let bytecode= self.vm.program.append(&mut program.to_bytes());
-                    println!("{:?}", bytecode);
-
+                    self.load_file(Path::new(tmp.trim()));
+                },
+                // The rest of the line is the path, spaces and all
+                [".load_file", ..] => {
+                    let path = buffer.strip_prefix(".load_file").unwrap_or_default().trim();
+                    self.load_file(Path::new(path));
+                },
+                // Runs the loaded program from the pc until it stops or reaches a breakpoint
+                [".run"] => {
+                    self.previous_registers = self.vm.registers;
+                    match self.vm.run() {
+                        Ok(reason) => println!("{}", reason),
+                        Err(fault) => self.fail(format!("VM fault: {}", fault)),
+                    }
+                },
+                [".reset"] => {
+                    self.vm.reset();
+                    self.previous_registers = self.vm.registers;
+                    println!("reset the VM, pc is {:04x}", self.vm.pc());
+                },
+                [command, ..] if command.starts_with('.') => {
+                    self.fail(format!("unknown command {}", command));
                 },
                 _ => {
-                    let parsed_program = program(CompleteStr(buffer));
-                if !parsed_program.is_ok() {
-                    println!("Unable to parse input");
-                    continue;
-                }
-                let (_, result) = parsed_program.unwrap();
-                let bytecode = result.to_bytes();
+                    let result = match parse_program(buffer) {
+                        Ok(result) => result,
+                        Err(e) => {
+                            self.fail(e);
+                            continue;
+                        }
+                    };
+                let bytecode = match result.to_bytes(&SymbolTable::new()) {
+                    Ok(bytecode) => bytecode,
+                    Err(errors) => {
+                        for error in errors {
+                            self.fail(error);
+                        }
+                        continue;
+                    }
+                };
                 println!("{:?}", bytecode);
-                // TODO: Make a function to let us add bytes to the VM
-                for byte in bytecode {
-                    self.vm.add_byte(byte);
+                self.run_typed(bytecode);
+                }
+            }
+        }
+        println!("Farewell! Have a great day!");
+        if self.failed {
+            1
+        } else {
+            0
+        }
+    }
+
+    // Appends the bytecode of a typed line and runs it. A pseudo-instruction like `li` can turn
+    // into several instructions, so this keeps going until all of them have run or control
+    // leaves them. When the pc was somewhere else, such as in a loaded program, it goes back
+    // there afterwards so the program carries on where it was.
+    fn run_typed(&mut self, bytecode: Vec<u8>) {
+        let start = self.vm.program.len();
+        let resume_at = self.vm.pc();
+        // TODO: Make a function to let us add bytes to the VM
+        for byte in bytecode {
+            self.vm.add_byte(byte);
+        }
+        let end = self.vm.program.len();
+        self.previous_registers = self.vm.registers;
+        self.vm.set_pc(start);
+        loop {
+            match self.vm.run_once() {
+                Ok(Some(reason)) => println!("{}", reason),
+                Ok(None) if (start..end).contains(&self.vm.pc()) => continue,
+                Ok(None) => {},
+                Err(fault) => self.fail(format!("VM fault: {}", fault)),
+            }
+            break;
+        }
+        if resume_at != start {
+            self.vm.set_pc(resume_at);
+        }
+    }
+
+    // Reports a command that went wrong, remembering it for the exit status
+    fn fail<T: fmt::Display>(&mut self, message: T) {
+        println!("{}", message);
+        self.failed = true;
+    }
+
+    // Assembles the file at `path` and loads it into the VM, reporting anything that goes wrong
+    fn load_file(&mut self, path: &Path) {
+        let mut contents = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            self.fail(format!("Unable to read {}: {}", path.display(), e));
+            return;
+        }
+        // The labels of the program already loaded stay in use unless this one loads
+        let mut assembler = Assembler::new();
+        match assembler.assemble(&contents) {
+            Ok(image) => {
+                let length = image.len();
+                if let Err(e) = self.vm.load_program(image) {
+                    self.fail(format!("Unable to load program: {}", e));
+                    return;
                 }
-                self.vm.run_once();
+                println!("loaded {} ({} bytes), pc is {:04x}", path.display(), length, self.vm.pc());
+                self.assembler = assembler;
+                self.previous_registers = self.vm.registers;
+                if let Some(helper) = self.editor.helper_mut() {
+                    helper.labels = self.assembler.symbols.code_labels().map(|(name, _)| name.to_string()).collect();
+                }
+            },
+            Err(errors) => {
+                for error in errors {
+                    self.fail(format!("{}: {}", path.display(), error));
+                }
+            }
+        }
+    }
+
+    // Runs up to `count` instructions, ignoring breakpoints, then shows where execution stopped
+    fn step(&mut self, count: usize) {
+        self.previous_registers = self.vm.registers;
+        for _ in 0..count {
+            match self.vm.run_once() {
+                Ok(None) => {},
+                Ok(Some(reason)) => {
+                    println!("{}", reason);
+                    return;
+                },
+                Err(fault) => {
+                    self.fail(format!("VM fault: {}", fault));
+                    return;
                 }
             }
         }
+        print!("{}", self.disassembly().listing_around(self.vm.pc(), 0));
+    }
+
+    // Shows every register in decimal and hex, marking with `*` the ones the last step or run
+    // changed, followed by the rest of the VM's state
+    fn print_registers(&self) {
+        for (row, registers) in self.vm.registers.chunks(4).enumerate() {
+            let mut line = String::new();
+            for (column, value) in registers.iter().enumerate() {
+                let index = row * 4 + column;
+                let changed = if *value != self.previous_registers[index] { '*' } else { ' ' };
+                line.push_str(&format!("{}${:<2} {:>11} {:#010x}   ", changed, index, value, value));
+            }
+            println!("{}", line.trim_end());
+        }
+        println!(
+            "pc {:04x}  equal_flag {}  remainder {}  heap {} bytes",
+            self.vm.pc(),
+            self.vm.equal_flag(),
+            self.vm.remainder(),
+            self.vm.heap().len()
+        );
+    }
+
+    // Turns a `.break` argument into an address: a code label, with or without its `@`, or a
+    // decimal or `0x` hex number
+    fn resolve_address(&self, target: &str) -> Result<usize, String> {
+        if let Some(address) = parse_number(target) {
+            return Ok(address);
+        }
+        if target.starts_with("0x") {
+            return Err(format!("`{}` is not an address", target));
+        }
+        let name = target.trim_start_matches('@');
+        match (self.assembler.symbols.symbol_type(name), self.assembler.symbols.symbol_value(name)) {
+            (Some(SymbolType::Label), Some(address)) => Ok(address as usize),
+            (Some(SymbolType::Data), _) => Err(format!("{} is a data label, not an instruction", name)),
+            _ => Err(format!("no label named {}", name)),
+        }
     }
-}
\ No newline at end of file
+
+    // The loaded program as instructions, using label names from the last assembled file.
+    // Typed instructions go in without a header, loaded files have one.
+    fn disassembly(&self) -> Disassembly {
+        let disassembly = match Disassembly::from_image(&self.vm.program) {
+            Ok(disassembly) => disassembly,
+            Err(_) => Disassembly::from_raw(&self.vm.program, 0),
+        };
+        disassembly.with_symbols(&self.assembler.symbols)
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(HISTORY_FILE))
+}
+
+// Reads a decimal or `0x` hex number typed as a command argument
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// Formats bytes 16 to a line, each line starting with the address of its first byte and ending
+// with the printable characters
+fn hexdump(bytes: &[u8], start: usize) -> String {
+    let mut dump = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = line
+            .iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+            .collect();
+        dump.push_str(&format!("{:08x}  {:<47}  |{}|\n", start + i * 16, hex.join(" "), text));
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_typed_expansion() {
+        let mut repl = REPL::new();
+        let program = parse_program("li $0 #100000").unwrap();
+        repl.run_typed(program.to_bytes(&SymbolTable::new()).unwrap());
+        assert_eq!(repl.vm.registers[0], 100_000);
+        assert_eq!(repl.vm.pc(), 8);
+        assert!(!repl.failed);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let mut repl = REPL::new();
+        assert!(!repl.failed);
+        repl.load_file(Path::new("/nonexistent/program.iasm"));
+        assert!(repl.failed);
+        assert!(repl.vm.program.is_empty());
+    }
+
+    #[test]
+    fn test_failed_load_keeps_labels() {
+        let dir = std::env::temp_dir();
+        let good = dir.join(format!("repl_good_{}.iasm", std::process::id()));
+        let bad = dir.join(format!("repl_bad_{}.iasm", std::process::id()));
+        std::fs::write(&good, "start: inc $0\nhlt").unwrap();
+        std::fs::write(&bad, "other: inc $0\njmp @nowhere").unwrap();
+        let mut repl = REPL::new();
+        repl.load_file(&good);
+        repl.load_file(&bad);
+        std::fs::remove_file(&good).unwrap();
+        std::fs::remove_file(&bad).unwrap();
+        assert!(repl.failed);
+        assert_eq!(repl.resolve_address("start"), Ok(64));
+        assert_eq!(repl.resolve_address("other"), Err("no label named other".to_string()));
+    }
+
+    #[test]
+    fn test_run_typed_after_load() {
+        let path = std::env::temp_dir().join(format!("repl_typed_{}.iasm", std::process::id()));
+        std::fs::write(&path, "load $1 #7\nhlt").unwrap();
+        let mut repl = REPL::new();
+        repl.load_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let pc = repl.vm.pc();
+        repl.run_typed(parse_program("inc $0").unwrap().to_bytes(&SymbolTable::new()).unwrap());
+        // The typed inc ran, not the loaded program's load
+        assert_eq!(repl.vm.registers[0], 1);
+        assert_eq!(repl.vm.registers[1], 0);
+        assert_eq!(repl.vm.pc(), pc);
+        assert!(!repl.failed);
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let bytes: Vec<u8> = (0..18).map(|b| b + 60).collect();
+        assert_eq!(
+            hexdump(&bytes, 0x20),
+            "00000020  3c 3d 3e 3f 40 41 42 43 44 45 46 47 48 49 4a 4b  |<=>?@ABCDEFGHIJK|\n\
+             00000030  4c 4d                                            |LM|\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("0x40"), Some(64));
+        assert_eq!(parse_number("12"), Some(12));
+        assert_eq!(parse_number("@loop"), None);
+    }
+}
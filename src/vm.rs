@@ -1,43 +1,208 @@
 use crate::instruction;
-const PIE_HEADER_PREFIX: [u8; 4] = [45, 50, 49, 45];
-const PIE_HEADER_LENGTH: usize = 64;
+use crate::instruction::OperandKind;
+use crate::pie::{self, HeaderError, PIE_HEADER_LENGTH};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+/// The most memory ALOC will let a program's heap grow to
+pub const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// How deep the stacks can grow unless a VM is given its own `stack_limit`
+pub const DEFAULT_STACK_LIMIT: usize = 1024;
+
+/// Why a program stopped without faulting
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExitReason {
+    /// A HLT instruction was executed. The program counter is left on it.
+    Halted,
+    /// The program counter reached the end of the program
+    EndOfProgram,
+    /// Execution paused before the instruction at this breakpoint address
+    Breakpoint(usize),
+}
+
+/// What went wrong when the VM could not carry on executing
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FaultKind {
+    IllegalOpcode,
+    DivideByZero,
+    /// An operand named a register the VM does not have
+    BadRegister(u8),
+    /// The program counter points beyond the end of the program, or a relative jump would take
+    /// it below 0
+    PcOutOfBounds,
+    /// The program ended in the middle of an instruction's operands
+    TruncatedInstruction,
+    HeapOverflow,
+    /// A load or store touched memory outside the allocated heap
+    HeapOutOfBounds,
+    /// A store, or an ALOC shrinking the heap, tried to change the program's read-only data
+    ReadOnlyWrite,
+    /// A CALL or PUSH went past the stack limit
+    StackOverflow,
+    /// A RET or POP found its stack empty
+    StackUnderflow,
+    /// A checked arithmetic instruction's result did not fit in 32 bits
+    Overflow,
+}
+
+/// A fault raised while executing, with the address and opcode byte of the faulting instruction
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct VmFault {
+    pub kind: FaultKind,
+    pub pc: usize,
+    /// None when the program counter did not point into the program
+    pub opcode: Option<u8>,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Halted => write!(f, "HLT encountered"),
+            ExitReason::EndOfProgram => write!(f, "reached the end of the program"),
+            ExitReason::Breakpoint(address) => write!(f, "stopped at breakpoint {:04x}", address),
+        }
+    }
+}
+
+impl fmt::Display for VmFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FaultKind::IllegalOpcode => write!(f, "illegal opcode")?,
+            FaultKind::DivideByZero => write!(f, "division by zero")?,
+            FaultKind::BadRegister(r) => write!(f, "no such register ${}", r)?,
+            FaultKind::PcOutOfBounds => write!(f, "program counter out of bounds")?,
+            FaultKind::TruncatedInstruction => write!(f, "truncated instruction")?,
+            FaultKind::HeapOverflow => write!(f, "heap overflow")?,
+            FaultKind::HeapOutOfBounds => write!(f, "heap access out of bounds")?,
+            FaultKind::ReadOnlyWrite => write!(f, "write to read-only data")?,
+            FaultKind::StackOverflow => write!(f, "stack overflow")?,
+            FaultKind::StackUnderflow => write!(f, "stack underflow")?,
+            FaultKind::Overflow => write!(f, "arithmetic overflow")?,
+        }
+        match self.opcode {
+            Some(byte) => write!(f, " at pc {} (opcode {})", self.pc, byte),
+            None => write!(f, " at pc {}", self.pc),
+        }
+    }
+}
+
+impl Error for VmFault {}
 
 pub struct VM{
    /// Array that simulates having hardware registers
-    pub registers: [i32; 32],
+    pub registers: [i32; instruction::REGISTER_COUNT],
+    /// A separate bank of registers for floating-point values
+    pub float_registers: [f64; instruction::REGISTER_COUNT],
     /// Program counter that tracks which byte is being executed
     pc: usize,
     /// The bytecode of the program being run
     pub program: Vec<u8>,
-    /// Contains the remainder of modulo division ops
-    remainder: usize,
+    /// Contains the remainder of the last DIV, which has the sign of the dividend
+    remainder: i32,
     /// Contains the result of the last comparison operation
     equal_flag: bool,
     //contains the head for our vm memory
-    heap:Vec<u8>
+    heap:Vec<u8>,
+    /// How many bytes at the start of the heap hold the program's read-only data
+    read_only: usize,
+    /// Return addresses of the subroutines currently being called
+    call_stack: Vec<usize>,
+    /// Values saved with PUSH
+    stack: Vec<i32>,
+    /// Maximum depth of both the call stack and the value stack
+    pub stack_limit: usize,
+    /// Addresses `run` and `resume` pause at before executing
+    breakpoints: BTreeSet<usize>
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM{
     pub fn new()->VM{
         VM{
             registers:[0;32],
+            float_registers:[0.0;32],
             pc:0,
             program:vec![],
             remainder:0,
             equal_flag:false,
-            heap:vec![]
+            heap:vec![],
+            read_only:0,
+            call_stack:vec![],
+            stack:vec![],
+            stack_limit:DEFAULT_STACK_LIMIT,
+            breakpoints:BTreeSet::new()
         }
     }
 
-    pub fn run(&mut self){
-        let mut is_done = false;
-        while !is_done {
-            is_done = self.execute_instruction();
+    /// Runs until the program halts, runs off its end, reaches a breakpoint or faults
+    pub fn run(&mut self) -> Result<ExitReason, VmFault> {
+        loop {
+            if self.breakpoints.contains(&self.pc) {
+                return Ok(ExitReason::Breakpoint(self.pc));
+            }
+            if let Some(reason) = self.execute_instruction()? {
+                return Ok(reason);
+            }
         }
     }
 
-    pub fn run_once(&mut self){
-        self.execute_instruction();
+    /// Like `run`, but first executes the instruction at the program counter even if it has a
+    /// breakpoint, so a program paused at one can carry on
+    pub fn resume(&mut self) -> Result<ExitReason, VmFault> {
+        if let Some(reason) = self.execute_instruction()? {
+            return Ok(reason);
+        }
+        self.run()
+    }
+
+    /// The address of the next instruction to execute
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Moves the program counter, such as to run an instruction typed into the REPL
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// The result of the last comparison
+    pub fn equal_flag(&self) -> bool {
+        self.equal_flag
+    }
+
+    /// The remainder left by the last DIV
+    pub fn remainder(&self) -> i32 {
+        self.remainder
+    }
+
+    /// The heap, starting with the program's read-only data
+    pub fn heap(&self) -> &[u8] {
+        &self.heap
+    }
+
+    /// Pauses `run` before the instruction at `address`. Returns false if there already was one.
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns false if there was no breakpoint at `address`
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    /// Executes a single instruction, returning the exit reason if it stopped the program
+    pub fn run_once(&mut self) -> Result<Option<ExitReason>, VmFault> {
+        self.execute_instruction()
     }
 
     pub fn add_byte(&mut self, b: u8) {
@@ -50,173 +215,643 @@ impl VM{
     }
 
 
+    /// Removes the program along with everything it left behind in the registers, stacks and
+    /// heap, keeping only the breakpoints
     pub fn clear_program(&mut self){
         self.program=vec![];
+        self.reset();
     }
 
     /// Processes the header of bytecode the VM wants to execute
     pub fn verify_header(&self) -> bool {
-        if self.program[0..4] != PIE_HEADER_PREFIX {
-            return false;
+        pie::verify_header(&self.program).is_ok()
+    }
+
+    /// Replaces the program with a PIE image, checking its header and starting execution at the
+    /// first instruction after it. The image's data section becomes the read-only start of the
+    /// heap, so data labels are heap addresses.
+    pub fn load_program(&mut self, image: Vec<u8>) -> Result<(), HeaderError> {
+        pie::verify_header(&image)?;
+        self.program = image;
+        self.reset();
+        Ok(())
+    }
+
+    /// Puts the VM back the way `load_program` leaves it, keeping the program and breakpoints:
+    /// registers, flags and stacks are cleared, the heap holds just the program's data and the
+    /// pc points at the first instruction. A program without a header starts at 0 with an empty
+    /// heap.
+    pub fn reset(&mut self) {
+        self.registers = [0; instruction::REGISTER_COUNT];
+        self.float_registers = [0.0; instruction::REGISTER_COUNT];
+        self.remainder = 0;
+        self.equal_flag = false;
+        self.call_stack.clear();
+        self.stack.clear();
+        if self.verify_header() {
+            let code_start = pie::code_start(&self.program);
+            self.heap = self.program[PIE_HEADER_LENGTH..code_start].to_vec();
+            self.pc = code_start;
+        } else {
+            self.heap.clear();
+            self.pc = 0;
         }
-        true
+        self.read_only = self.heap.len();
     }
 
-   
-    
-    fn execute_instruction(&mut self)->bool{
-         // If our program counter has exceeded the length of the program itself, something has
-            // gone awry
-            if self.pc>=self.program.len(){
-                return true
-            }
-            match self.decode_opcode(){
+    fn execute_instruction(&mut self) -> Result<Option<ExitReason>, VmFault> {
+        // Running exactly to the end of the program is a normal way to stop, but a program
+        // counter beyond that means a jump went somewhere it should not have
+        if self.pc == self.program.len() {
+            return Ok(Some(ExitReason::EndOfProgram));
+        }
+        let pc = self.pc;
+        let opcode = self.program.get(pc).copied();
+        if opcode.is_none() {
+            return Err(VmFault { kind: FaultKind::PcOutOfBounds, pc, opcode });
+        }
+        self.execute_opcode().map_err(|kind| VmFault { kind, pc, opcode })
+    }
+
+    fn execute_opcode(&mut self) -> Result<Option<ExitReason>, FaultKind> {
+            let opcode = self.decode_opcode();
+            self.check_operands(opcode)?;
+            match opcode {
                 //1. Decode the first 8 bits and see LOAD 2. Decode the next 8 bits and use it to get the register 3. Decode the next 16 bits (split into 2 u8s) into an integer 4. Store them in the register
                 instruction::Opcode::LOAD=>{
-                    let register = self.next_8_bits() as usize; // We cast to usize so we can use it as an index into the array
-                    let number = self.next_16_bits() as u16;
-                    self.registers[register] = number as i32; // Our registers are i32s, so we need to cast it. We'll cover that later.
+                    let register = self.next_register()?; // Checked to be a valid index into the array
+                    let number = self.next_16_bits()?;
+                    self.registers[register] = number as i16 as i32; // The immediate is sign-extended, so LOAD covers -32768 to 32767
+                },
+                //LUI replaces the upper half of a register, so LOAD followed by LUI builds any 32-bit value
+                instruction::Opcode::LUI=>{
+                    let register = self.next_register()?;
+                    let upper = self.next_16_bits()? as i32;
+                    self.registers[register] = (upper << 16) | (self.registers[register] & 0xffff);
+                },
+                //copy the first register into the second
+                instruction::Opcode::MOV=>{
+                    let value = self.registers[self.next_register()?];
+                    self.registers[self.next_register()?] = value;
+                    self.next_8_bits()?;
                 },
                 //LOAD $0 #10 LOAD $1 #15ADD $0 $1 $2
+                //ADD, SUB and MUL wrap around on overflow in every build; the V variants below
+                //fault instead
                 instruction::Opcode::ADD=>{
-                    let register1=self.registers[self.next_8_bits() as usize];
-                    let register2=self.registers[self.next_8_bits() as usize];
-                    self.registers[self.next_8_bits() as usize]= register1+register2;
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1.wrapping_add(register2);
                 },
                 instruction::Opcode::SUB=>{
-                    let register1=self.registers[self.next_8_bits() as usize];
-                    let register2=self.registers[self.next_8_bits() as usize];
-                    self.registers[self.next_8_bits() as usize]=register1-register2;
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1.wrapping_sub(register2);
                 },
                 instruction::Opcode::MUL=>{
-                    let register1=self.registers[self.next_8_bits() as usize];
-                    let register2=self.registers[self.next_8_bits() as usize];
-                    self.registers[self.next_8_bits() as usize]=register1*register2;
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1.wrapping_mul(register2);
+                },
+                instruction::Opcode::ADDV=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    let result=register1.checked_add(register2).ok_or(FaultKind::Overflow)?;
+                    self.registers[self.next_register()?]=result;
+                },
+                instruction::Opcode::SUBV=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    let result=register1.checked_sub(register2).ok_or(FaultKind::Overflow)?;
+                    self.registers[self.next_register()?]=result;
+                },
+                instruction::Opcode::MULV=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    let result=register1.checked_mul(register2).ok_or(FaultKind::Overflow)?;
+                    self.registers[self.next_register()?]=result;
+                },
+                //bitwise operations work on the raw 32 bits of the registers
+                instruction::Opcode::AND=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1&register2;
+                },
+                instruction::Opcode::OR=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1|register2;
+                },
+                instruction::Opcode::XOR=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1^register2;
+                },
+                instruction::Opcode::NOT=>{
+                    let register1=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?] = !register1;
+                    self.next_8_bits()?;
                 },
-                //When we come across a DIV opcode, what we want to do is divide it, store the quotient in the register, and the remainder in the remainder attribute of the VM
+                //shifts take the amount from the low 5 bits of the second register, so shifting
+                //by 32 or more wraps around the same way as on most hardware
+                instruction::Opcode::SHL=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1.wrapping_shl(register2 as u32);
+                },
+                instruction::Opcode::SHR=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=(register1 as u32).wrapping_shr(register2 as u32) as i32;
+                },
+                instruction::Opcode::SAR=>{
+                    let register1=self.registers[self.next_register()?];
+                    let register2=self.registers[self.next_register()?];
+                    self.registers[self.next_register()?]=register1.wrapping_shr(register2 as u32);
+                },
+                //When we come across a DIV opcode, what we want to do is divide it, store the quotient in the register, and the remainder in the remainder attribute of the VM.
+                //Division truncates toward zero, so the remainder takes the sign of the dividend: -7 / 2 is -3 remainder -1.
+                //i32::MIN / -1 wraps to i32::MIN with a remainder of 0.
                instruction::Opcode::DIV=>{
-                let register1 = self.registers[self.next_8_bits() as usize];
-                let register2 = self.registers[self.next_8_bits() as usize];
-                self.registers[self.next_8_bits() as usize] = register1 / register2;
-                self.remainder = (register1 % register2) as usize;
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                if register2 == 0 {
+                    return Err(FaultKind::DivideByZero);
+                }
+                self.registers[self.next_register()?] = register1.wrapping_div(register2);
+                self.remainder = register1.wrapping_rem(register2);
+               },
+               //MOD stores just the remainder, with the same truncating semantics as DIV
+               instruction::Opcode::MOD=>{
+                let register1 = self.registers[self.next_register()?];
+                let register2 = self.registers[self.next_register()?];
+                if register2 == 0 {
+                    return Err(FaultKind::DivideByZero);
+                }
+                self.registers[self.next_register()?] = register1.wrapping_rem(register2);
+               },
+               //copy the remainder of the last DIV into a register
+               instruction::Opcode::MFR=>{
+                self.registers[self.next_register()?] = self.remainder;
+                self.skip_padding(2)?;
                },
                //jump to an instruction in the program
                instruction::Opcode::JMP=>{
-                let target = self.registers[self.next_8_bits() as usize];
+                let target = self.registers[self.next_register()?];
+                self.skip_padding(2)?;
                 self.pc = target as usize;
                },
-               //relative jump forward
+               //relative jumps count from the end of the jump instruction, so an offset of 0
+               //carries on with the next one. The register holds a signed distance.
                instruction::Opcode::JMPF => {
-                let value = self.registers[self.next_8_bits() as usize] as usize;
-                self.pc += value;
+                let value = self.registers[self.next_register()?];
+                self.skip_padding(2)?;
+                self.jump_relative(i64::from(value))?;
             },
             //check equality and store them in the equal flag
             instruction::Opcode::EQ=>{
-                let register1= self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1= self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag = register1 == register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             },
             //check if not equal
             instruction::Opcode::NEQ=>{
-                let register1= self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1= self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag = register1 != register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             },
             //check for greater than
             instruction::Opcode::GT=>{
-                let register1=self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1=self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag= register1>register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             },
             //check less than
             instruction::Opcode::LT=>{
-                let register1=self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1=self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag=register1<register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             },
             //greater than or equal to
             instruction::Opcode::GTQ=>{
-                let register1=self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1=self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag=register1>=register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             },
             //less than or equal to
             instruction::Opcode::LTQ=>{
-                let register1=self.registers[self.next_8_bits() as usize];
-                let register2=self.registers[self.next_8_bits() as usize];
+                let register1=self.registers[self.next_register()?];
+                let register2=self.registers[self.next_register()?];
                 self.equal_flag=register1<=register2;
-                self.next_8_bits();
+                self.next_8_bits()?;
             }
             //ump If Equal will take one register as an argument, and if equal_flag is true, will jump to the value stored in that register
             instruction::Opcode::JEQ=>{
-                let register = self.next_8_bits() as usize;
+                let register = self.next_register()?;
                 let target = self.registers[register];
+                self.skip_padding(2)?;
                 if self.equal_flag {
                     self.pc = target as usize;
                 }
             },
             //relative jump backward
             instruction::Opcode::JMPB=>{
-                let value=self.registers[self.next_8_bits()as usize] as usize;
-                self.pc+=value;
+                let value=self.registers[self.next_register()?];
+                self.skip_padding(2)?;
+                self.jump_relative(-i64::from(value))?;
+            },
+            //jump to an absolute address given as an immediate, which is how labels are assembled
+            instruction::Opcode::JMPI=>{
+                self.pc = self.next_16_bits()? as usize;
+            },
+            instruction::Opcode::JEQI=>{
+                let target = self.next_16_bits()? as usize;
+                self.next_8_bits()?;
+                if self.equal_flag {
+                    self.pc = target;
+                }
+            },
+            instruction::Opcode::JNEQ=>{
+                let target = self.registers[self.next_register()?];
+                self.skip_padding(2)?;
+                if !self.equal_flag {
+                    self.pc = target as usize;
+                }
+            },
+            instruction::Opcode::JNEQI=>{
+                let target = self.next_16_bits()? as usize;
+                self.next_8_bits()?;
+                if !self.equal_flag {
+                    self.pc = target;
+                }
+            },
+            //branch on the equal flag by a signed offset from the end of this instruction
+            instruction::Opcode::BT=>{
+                let offset = self.next_16_bits()? as i16;
+                self.next_8_bits()?;
+                if self.equal_flag {
+                    self.jump_relative(i64::from(offset))?;
+                }
+            },
+            instruction::Opcode::BF=>{
+                let offset = self.next_16_bits()? as i16;
+                self.next_8_bits()?;
+                if !self.equal_flag {
+                    self.jump_relative(i64::from(offset))?;
+                }
+            },
+            //fused compare-and-branch by a signed offset from the end of this instruction; the
+            //equal flag is left alone
+            instruction::Opcode::BEQ=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 == register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            instruction::Opcode::BNE=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 != register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            instruction::Opcode::BLT=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 < register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            instruction::Opcode::BGT=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 > register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            instruction::Opcode::BLE=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 <= register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            instruction::Opcode::BGE=>{
+                let (register1, register2, offset) = self.next_branch_operands()?;
+                if register1 >= register2 {
+                    self.jump_relative(offset)?;
+                }
+            },
+            //relative jump forward by an immediate, counted from the end of this instruction
+            instruction::Opcode::JMPFI=>{
+                let value = self.next_16_bits()?;
+                self.next_8_bits()?;
+                self.jump_relative(i64::from(value))?;
+            },
+            //relative jump backward by an immediate, counted from the end of this instruction
+            instruction::Opcode::JMPBI=>{
+                let value = self.next_16_bits()?;
+                self.next_8_bits()?;
+                self.jump_relative(-i64::from(value))?;
             },
             //aloc extends the size of the heap vector by the amount in the register given as an argument.
             instruction::Opcode::ALOC=>{
-                let register = self.next_8_bits() as usize;
-                let bytes = self.registers[register];
-                let new_end = self.heap.len() as i32 + bytes;
+                let register = self.next_register()?;
+                let bytes = self.registers[register] as i64;
+                let new_end = self.heap.len() as i64 + bytes;
+                if new_end < 0 || new_end > MAX_HEAP_SIZE as i64 {
+                    return Err(FaultKind::HeapOverflow);
+                }
+                // A negative count frees memory, but only what was allocated after the data
+                if new_end < self.read_only as i64 {
+                    return Err(FaultKind::ReadOnlyWrite);
+                }
                 self.heap.resize(new_end as usize, 0);
+                self.skip_padding(2)?;
+            },
+            //heap loads and stores address memory as a base register plus a one byte offset
+            instruction::Opcode::LOADB=>{
+                let register = self.next_register()?;
+                let address = self.next_heap_address(1)?;
+                self.registers[register] = self.heap[address] as i32;
+            },
+            instruction::Opcode::LOADW=>{
+                let register = self.next_register()?;
+                let address = self.next_heap_address(4)?;
+                let mut word = [0; 4];
+                word.copy_from_slice(&self.heap[address..address + 4]);
+                self.registers[register] = i32::from_be_bytes(word);
+            },
+            instruction::Opcode::STOREB=>{
+                let value = self.registers[self.next_register()?];
+                let address = self.next_writable_address(1)?;
+                self.heap[address] = value as u8;
+            },
+            instruction::Opcode::STOREW=>{
+                let value = self.registers[self.next_register()?];
+                let address = self.next_writable_address(4)?;
+                self.heap[address..address + 4].copy_from_slice(&value.to_be_bytes());
+            },
+            //floats are stored on the heap as 8 big-endian bytes
+            instruction::Opcode::LOADF=>{
+                let register = self.next_float_register()?;
+                let address = self.next_heap_address(8)?;
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&self.heap[address..address + 8]);
+                self.float_registers[register] = f64::from_be_bytes(bytes);
+            },
+            instruction::Opcode::STOREF=>{
+                let value = self.float_registers[self.next_float_register()?];
+                let address = self.next_writable_address(8)?;
+                self.heap[address..address + 8].copy_from_slice(&value.to_be_bytes());
+            },
+            //float arithmetic follows IEEE 754, so dividing by zero gives an infinity or NaN
+            //rather than a fault
+            instruction::Opcode::ADDF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 + register2;
+            },
+            instruction::Opcode::SUBF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 - register2;
+            },
+            instruction::Opcode::MULF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 * register2;
+            },
+            instruction::Opcode::DIVF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.float_registers[self.next_float_register()?] = register1 / register2;
+            },
+            //float comparisons set the same flag as the integer ones; any comparison with NaN
+            //is false except NEQF
+            instruction::Opcode::EQF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 == register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::NEQF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 != register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::GTF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 > register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::LTF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 < register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::GTEF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 >= register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::LTEF=>{
+                let register1 = self.float_registers[self.next_float_register()?];
+                let register2 = self.float_registers[self.next_float_register()?];
+                self.equal_flag = register1 <= register2;
+                self.next_8_bits()?;
+            },
+            instruction::Opcode::ITOF=>{
+                let value = self.registers[self.next_register()?];
+                self.float_registers[self.next_float_register()?] = f64::from(value);
+                self.next_8_bits()?;
+            },
+            //truncates toward zero, saturating at the i32 limits; NaN becomes 0
+            instruction::Opcode::FTOI=>{
+                let value = self.float_registers[self.next_float_register()?];
+                self.registers[self.next_register()?] = value as i32;
+                self.next_8_bits()?;
             },
-            
+
+            //increment and decrement a register in place, wrapping around at the i32 limits
+            instruction::Opcode::INC=>{
+                let register = self.next_register()?;
+                self.registers[register] = self.registers[register].wrapping_add(1);
+                self.skip_padding(2)?;
+            },
+            instruction::Opcode::DEC=>{
+                let register = self.next_register()?;
+                self.registers[register] = self.registers[register].wrapping_sub(1);
+                self.skip_padding(2)?;
+            },
+            instruction::Opcode::NOP=>{
+                self.skip_padding(3)?;
+            },
+            //call saves the address of the next instruction so RET can come back to it
+            instruction::Opcode::CALL=>{
+                let target = self.registers[self.next_register()?];
+                self.skip_padding(2)?;
+                self.push_return_address()?;
+                self.pc = target as usize;
+            },
+            instruction::Opcode::CALLI=>{
+                let target = self.next_16_bits()? as usize;
+                self.skip_padding(1)?;
+                self.push_return_address()?;
+                self.pc = target;
+            },
+            instruction::Opcode::RET=>{
+                self.skip_padding(3)?;
+                self.pc = self.call_stack.pop().ok_or(FaultKind::StackUnderflow)?;
+            },
+            instruction::Opcode::PUSH=>{
+                let value = self.registers[self.next_register()?];
+                self.skip_padding(2)?;
+                if self.stack.len() >= self.stack_limit {
+                    return Err(FaultKind::StackOverflow);
+                }
+                self.stack.push(value);
+            },
+            instruction::Opcode::POP=>{
+                let register = self.next_register()?;
+                self.skip_padding(2)?;
+                self.registers[register] = self.stack.pop().ok_or(FaultKind::StackUnderflow)?;
+            },
+                //stay on the HLT, so running again halts again instead of decoding its padding
                 instruction::Opcode::HLT=>{
-                    println!("HLT encountered");
-                    return true;
+                    self.pc -= 1;
+                    return Ok(Some(ExitReason::Halted));
                 },  
                 _=>{
-                    println!("unrecognized instruction");
-                    return true;
+                    return Err(FaultKind::IllegalOpcode);
                 }  
             }
-            false
+            Ok(None)
     }
+
     fn decode_opcode(&mut self) -> instruction::Opcode {
         //get cureent opcode and move to next byte
         let opcode = instruction::Opcode::from(self.program[self.pc]);
         self.pc += 1;
-        return opcode;
+        opcode
     }
-    fn next_8_bits(&mut self) -> u8 {
-        let result = self.program[self.pc];
+    fn next_8_bits(&mut self) -> Result<u8, FaultKind> {
+        let result = *self.program.get(self.pc).ok_or(FaultKind::TruncatedInstruction)?;
         self.pc += 1;
-        return result;
+        Ok(result)
     }
-    
-    fn next_16_bits(&mut self) -> u16 {
+
+    fn next_16_bits(&mut self) -> Result<u16, FaultKind> {
+        if self.pc + 2 > self.program.len() {
+            return Err(FaultKind::TruncatedInstruction);
+        }
         let result = ((self.program[self.pc] as u16) << 8) | self.program[self.pc + 1] as u16;
         self.pc += 2;
-        return result;
+        Ok(result)
     }
 
-}
- pub fn prepend_header(mut b: Vec<u8>) -> Vec<u8> {
-        let mut prepension = vec![];
-        for byte in PIE_HEADER_PREFIX.iter() {
-            prepension.push(byte.clone());
+    // Reads a base register and offset, checking that `size` bytes from there are in the heap
+    fn next_heap_address(&mut self, size: usize) -> Result<usize, FaultKind> {
+        let base = self.registers[self.next_register()?] as i64;
+        let offset = self.next_8_bits()? as i64;
+        let address = base + offset;
+        if address < 0 || address as usize + size > self.heap.len() {
+            return Err(FaultKind::HeapOutOfBounds);
+        }
+        Ok(address as usize)
+    }
+
+    // Moves the program counter by `offset` from where it is now, refusing to go below 0
+    fn jump_relative(&mut self, offset: i64) -> Result<(), FaultKind> {
+        let target = self.pc as i64 + offset;
+        if target < 0 {
+            return Err(FaultKind::PcOutOfBounds);
+        }
+        self.pc = target as usize;
+        Ok(())
+    }
+
+    // Reads the two registers a fused branch compares and its offset
+    fn next_branch_operands(&mut self) -> Result<(i32, i32, i64), FaultKind> {
+        let register1 = self.registers[self.next_register()?];
+        let register2 = self.registers[self.next_register()?];
+        let offset = self.next_8_bits()? as i8;
+        Ok((register1, register2, i64::from(offset)))
+    }
+
+    fn push_return_address(&mut self) -> Result<(), FaultKind> {
+        if self.call_stack.len() >= self.stack_limit {
+            return Err(FaultKind::StackOverflow);
         }
-        while prepension.len() <= PIE_HEADER_LENGTH {
-            prepension.push(0);
+        self.call_stack.push(self.pc);
+        Ok(())
+    }
+
+    // Like `next_heap_address`, but also refuses addresses in the read-only data
+    fn next_writable_address(&mut self, size: usize) -> Result<usize, FaultKind> {
+        let address = self.next_heap_address(size)?;
+        if address < self.read_only {
+            return Err(FaultKind::ReadOnlyWrite);
+        }
+        Ok(address)
+    }
+
+    // Moves past the unused bytes that pad an instruction out to 4 bytes
+    fn skip_padding(&mut self, bytes: usize) -> Result<(), FaultKind> {
+        for _ in 0..bytes {
+            self.next_8_bits()?;
+        }
+        Ok(())
+    }
+
+    // Checks the operands after the opcode against its signature before anything runs, so a
+    // malformed instruction faults without having changed any state
+    fn check_operands(&self, opcode: instruction::Opcode) -> Result<(), FaultKind> {
+        let mut at = self.pc;
+        for kind in opcode.operands() {
+            if at + kind.size() > self.program.len() {
+                return Err(FaultKind::TruncatedInstruction);
+            }
+            let register = self.program[at];
+            if *kind == OperandKind::Register && register as usize >= self.registers.len() {
+                return Err(FaultKind::BadRegister(register));
+            }
+            if *kind == OperandKind::FloatRegister && register as usize >= self.float_registers.len() {
+                return Err(FaultKind::BadRegister(register));
+            }
+            at += kind.size();
         }
-        prepension.append(&mut b);
-        prepension
+        Ok(())
     }
 
+    // Reads a float register operand, checking that it names one of the VM's float registers
+    fn next_float_register(&mut self) -> Result<usize, FaultKind> {
+        let register = self.next_8_bits()?;
+        if register as usize >= self.float_registers.len() {
+            return Err(FaultKind::BadRegister(register));
+        }
+        Ok(register as usize)
+    }
+
+    // Reads a register operand, checking that it names one of the VM's registers
+    fn next_register(&mut self) -> Result<usize, FaultKind> {
+        let register = self.next_8_bits()?;
+        if register as usize >= self.registers.len() {
+            return Err(FaultKind::BadRegister(register));
+        }
+        Ok(register as usize)
+    }
+
+}
 #[cfg(test)]
 mod tests{
+    #![allow(clippy::bool_assert_comparison)]
+
     use super::*;
 
 #[test]
@@ -230,10 +865,10 @@ fn test_eq_opcode() {
     test_vm.registers[0] = 10;
     test_vm.registers[1] = 10;
     test_vm.program = vec![9, 0, 1, 0, 9, 0, 1, 0];
-    test_vm.run_once();
+    test_vm.run_once().unwrap();
     assert_eq!(test_vm.equal_flag, true);
     test_vm.registers[1] = 20;
-    test_vm.run_once();
+    test_vm.run_once().unwrap();
     assert_eq!(test_vm.equal_flag, false);
 }
 
@@ -241,26 +876,27 @@ fn test_eq_opcode() {
 #[test]
 fn test_jmpf_opcode() {
     let mut test_vm = VM::new();
-    test_vm.registers[0] = 2;
-    test_vm.program = vec![8, 0, 0, 0, 6, 0, 0, 0];
-    test_vm.run_once();
-    assert_eq!(test_vm.pc, 4);
+    test_vm.registers[0] = 4;
+    test_vm.program = vec![7, 0, 0, 0, 6, 0, 0, 0];
+    test_vm.run_once().unwrap();
+    assert_eq!(test_vm.pc, 8);
 }
 #[test]
     fn test_jmpb_opcode() {
         let mut test_vm = VM::new();
         test_vm.registers[1] = 6;
-        test_vm.program = vec![0, 0, 0, 10, 8, 1, 0, 0];//little endian representation for opcode 9
-        test_vm.run_once();
-       test_vm.run_once();
-        assert_eq!(test_vm.pc, 12);
+        test_vm.program = vec![0, 0, 0, 10, 8, 1, 0, 0];
+        test_vm.run_once().unwrap();
+       test_vm.run_once().unwrap();
+        // backward from the end of the JMPB at 8
+        assert_eq!(test_vm.pc, 2);
     }
 
 #[test]
 fn test_load_opcode() {
   let mut test_vm = VM::new();
   test_vm.program = vec![0, 0, 1, 244]; // Remember, this is how we represent 500 using two u8s in little endian format
-  test_vm.run();
+  test_vm.run().unwrap();
   assert_eq!(test_vm.registers[0], 500);
 }
 
@@ -269,16 +905,23 @@ fn test_load_opcode() {
 fn test_jmp_opcode() {
     let mut test_vm = VM::new();
     test_vm.registers[0] = 1;
-    test_vm.program = vec![7, 0, 0, 0];
-    test_vm.run_once();
-    assert_eq!(test_vm.pc, 3);
+    test_vm.program = vec![6, 0, 0, 0];
+    test_vm.run_once().unwrap();
+    assert_eq!(test_vm.pc, 1);
 }
     #[test]
     fn test_opcode_hlt() {
       let mut test_vm = VM::new();
       let test_bytes = vec![0,0,0,0]; //little endian representation
       test_vm.program = test_bytes;
-      test_vm.run();
+      assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+      assert_eq!(test_vm.pc, 4);
+      test_vm.program.append(&mut vec![5, 0, 0, 0]);
+      assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+      assert_eq!(test_vm.pc, 4);
+      assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+      assert_eq!(test_vm.resume(), Ok(ExitReason::Halted));
+      assert_eq!(test_vm.run_once(), Ok(Some(ExitReason::Halted)));
       assert_eq!(test_vm.pc, 4);
     }
 
@@ -287,7 +930,8 @@ fn test_jmp_opcode() {
       let mut test_vm = VM::new();
       let test_bytes = vec![200,0,0,0];
       test_vm.program = test_bytes;
-      test_vm.run();
+      let fault = test_vm.run().unwrap_err();
+      assert_eq!(fault, VmFault { kind: FaultKind::IllegalOpcode, pc: 0, opcode: Some(200) });
       assert_eq!(test_vm.pc, 1);
     }
 
@@ -298,13 +942,13 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 20;
         test_vm.registers[1] = 10;
         test_vm.program = vec![11, 0, 1, 0, 11, 0, 1, 0, 11, 0, 1, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
         test_vm.registers[0] = 10;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
         test_vm.registers[0] = 5;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
     }
 
@@ -314,10 +958,10 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 10;
         test_vm.registers[1] = 20;
         test_vm.program = vec![10, 0, 1, 0, 10, 0, 1, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
         test_vm.registers[1] = 10;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
     }
 
@@ -327,13 +971,13 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 20;
         test_vm.registers[1] = 10;
         test_vm.program = vec![12, 0, 1, 0, 12, 0, 1, 0, 12, 0, 1, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
         test_vm.registers[0] = 10;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
         test_vm.registers[0] = 5;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
     }
 
@@ -343,13 +987,13 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 20;
         test_vm.registers[1] = 10;
         test_vm.program = vec![13, 0, 1, 0, 13, 0, 1, 0, 13, 0, 1, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
         test_vm.registers[0] = 10;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
         test_vm.registers[0] = 5;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
     }
 
@@ -359,13 +1003,13 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 20;
         test_vm.registers[1] = 10;
         test_vm.program = vec![14, 0, 1, 0, 14, 0, 1, 0, 14, 0, 1, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, true);
         test_vm.registers[0] = 10;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
         test_vm.registers[0] = 5;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.equal_flag, false);
     }
 
@@ -375,7 +1019,7 @@ fn test_jmp_opcode() {
         test_vm.registers[0] = 7;
         test_vm.equal_flag = true;
         test_vm.program = vec![15, 0, 0, 0, 15, 0, 0, 0, 15, 0, 0, 0];
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 7);
     }
     #[test]
@@ -383,8 +1027,585 @@ fn test_aloc_opcode() {
     let mut test_vm = VM::new();
     test_vm.registers[0] = 500;
     test_vm.program = vec![17, 0, 0, 0];
-    test_vm.run_once();
+    test_vm.run_once().unwrap();
     assert_eq!(test_vm.heap.len(), 500);
+    assert_eq!(test_vm.pc, 4);
 }
 
+
+    #[test]
+    fn test_jmpi_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![21, 0, 8, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+    }
+
+    #[test]
+    fn test_jeqi_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![22, 0, 12, 0, 22, 0, 12, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+        test_vm.equal_flag = true;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_jmpfi_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![23, 0, 8, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+
+    #[test]
+    fn test_div_by_zero_fault() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![4, 0, 1, 2];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::DivideByZero, pc: 0, opcode: Some(4) });
+    }
+
+    #[test]
+    fn test_div_and_mfr_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -7;
+        test_vm.registers[1] = 2;
+        test_vm.program = vec![4, 0, 1, 2, 42, 3, 0, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], -3);
+        assert_eq!(test_vm.registers[3], -1);
+    }
+
+    #[test]
+    fn test_mod_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 7;
+        test_vm.registers[1] = -2;
+        test_vm.registers[2] = -7;
+        test_vm.registers[3] = i32::MIN;
+        test_vm.registers[4] = -1;
+        test_vm.program = vec![41, 0, 1, 5, 41, 2, 1, 6, 41, 3, 4, 7, 4, 3, 4, 8];
+        test_vm.run().unwrap();
+        // The remainder takes the sign of the dividend
+        assert_eq!(test_vm.registers[5], 1);
+        assert_eq!(test_vm.registers[6], -1);
+        assert_eq!(test_vm.registers[7], 0);
+        assert_eq!(test_vm.registers[8], i32::MIN);
+    }
+
+    #[test]
+    fn test_mod_by_zero_fault() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 10;
+        test_vm.program = vec![41, 0, 1, 2];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::DivideByZero, pc: 0, opcode: Some(41) });
+    }
+
+    #[test]
+    fn test_arithmetic_wraps() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = i32::MAX;
+        test_vm.registers[1] = 1;
+        test_vm.registers[2] = i32::MIN;
+        test_vm.program = vec![1, 0, 1, 3, 2, 2, 1, 4, 3, 0, 0, 5, 1, 0, 0, 6];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[3], i32::MIN);
+        assert_eq!(test_vm.registers[4], i32::MAX);
+        assert_eq!(test_vm.registers[5], 1);
+        assert_eq!(test_vm.registers[6], -2);
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = i32::MAX - 1;
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![43, 0, 1, 0, 43, 0, 1, 2];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(test_vm.registers[0], i32::MAX);
+        assert_eq!(fault, VmFault { kind: FaultKind::Overflow, pc: 4, opcode: Some(43) });
+        // A faulting instruction leaves its destination alone
+        assert_eq!(test_vm.registers[2], 0);
+
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = i32::MIN;
+        test_vm.registers[1] = 1;
+        test_vm.program = vec![44, 0, 1, 2];
+        assert_eq!(test_vm.run().unwrap_err().kind, FaultKind::Overflow);
+
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 0x1_0000;
+        test_vm.registers[1] = 0x8000;
+        test_vm.program = vec![45, 1, 1, 2, 45, 0, 1, 3];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(test_vm.registers[2], 0x4000_0000);
+        assert_eq!(fault.kind, FaultKind::Overflow);
+    }
+
+    #[test]
+    fn test_mov_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -9;
+        test_vm.program = vec![46, 0, 1, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[1], -9);
+        assert_eq!(test_vm.registers[0], -9);
+    }
+
+    #[test]
+    fn test_lui_opcode() {
+        let mut test_vm = VM::new();
+        // load $0 #-2 sign-extends to 0xfffffffe, then lui $0 #0x1234 replaces the upper half
+        test_vm.program = vec![0, 0, 0xff, 0xfe, 47, 0, 0x12, 0x34];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[0], 0x1234_fffe);
+    }
+
+    #[test]
+    fn test_float_load_store_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.heap = vec![0; 16];
+        test_vm.heap[..8].copy_from_slice(&1.5f64.to_be_bytes());
+        test_vm.program = vec![48, 2, 0, 0, 49, 2, 0, 8];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.float_registers[2], 1.5);
+        assert_eq!(&test_vm.heap[8..], &1.5f64.to_be_bytes());
+    }
+
+    #[test]
+    fn test_float_arithmetic_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 3.0;
+        test_vm.float_registers[1] = 0.5;
+        test_vm.program = vec![50, 0, 1, 2, 51, 0, 1, 3, 52, 0, 1, 4, 53, 0, 1, 5, 53, 0, 6, 7];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.float_registers[2], 3.5);
+        assert_eq!(test_vm.float_registers[3], 2.5);
+        assert_eq!(test_vm.float_registers[4], 1.5);
+        assert_eq!(test_vm.float_registers[5], 6.0);
+        assert_eq!(test_vm.float_registers[7], f64::INFINITY);
+    }
+
+    #[test]
+    fn test_float_comparison_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 1.0;
+        test_vm.float_registers[1] = 2.0;
+        test_vm.float_registers[2] = f64::NAN;
+        let mut compare = |code: u8, a: u8, b: u8| {
+            test_vm.pc = 0;
+            test_vm.program = vec![code, a, b, 0];
+            test_vm.run_once().unwrap();
+            test_vm.equal_flag
+        };
+        assert!(compare(54, 0, 0));
+        assert!(compare(55, 0, 1));
+        assert!(compare(56, 1, 0));
+        assert!(!compare(57, 1, 0));
+        assert!(compare(58, 0, 0));
+        assert!(!compare(59, 1, 0));
+        assert!(!compare(54, 2, 2));
+        assert!(compare(55, 2, 2));
+    }
+
+    #[test]
+    fn test_float_conversion_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -7;
+        test_vm.float_registers[1] = -2.75;
+        test_vm.float_registers[2] = 1e20;
+        test_vm.program = vec![60, 0, 0, 0, 61, 1, 1, 0, 61, 2, 2, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.float_registers[0], -7.0);
+        assert_eq!(test_vm.registers[1], -2);
+        assert_eq!(test_vm.registers[2], i32::MAX);
+    }
+
+    #[test]
+    fn test_jneq_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 8;
+        test_vm.program = vec![62, 0, 0, 0, 63, 0, 12, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+        test_vm.pc = 4;
+        test_vm.equal_flag = true;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 8);
+    }
+
+    #[test]
+    fn test_branch_on_flag_opcodes() {
+        let mut test_vm = VM::new();
+        // bf #-4 loops on itself until the flag is set; bt #4 skips one instruction
+        test_vm.program = vec![65, 0xff, 0xfc, 0, 64, 0, 4, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 0);
+        test_vm.equal_flag = true;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+    }
+
+    #[test]
+    fn test_fused_branch_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -1;
+        test_vm.registers[1] = 1;
+        let mut branch = |code: u8, offset: i8| {
+            test_vm.pc = 0;
+            test_vm.program = vec![code, 0, 1, offset as u8];
+            test_vm.run_once().unwrap();
+            test_vm.pc
+        };
+        assert_eq!(branch(66, 8), 4);
+        assert_eq!(branch(67, 8), 12);
+        assert_eq!(branch(68, 8), 12);
+        assert_eq!(branch(69, 8), 4);
+        assert_eq!(branch(70, 0), 4);
+        assert_eq!(branch(71, 8), 4);
+        assert_eq!(branch(67, -4), 0);
+        let fault = VM { program: vec![66, 0, 1, 0xf8], ..VM::new() }.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::PcOutOfBounds);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut test_vm = VM::new();
+        // inc $0 three times, then hlt
+        test_vm.program = vec![18, 0, 0, 0, 18, 0, 0, 0, 18, 0, 0, 0, 5, 0, 0, 0];
+        assert!(test_vm.add_breakpoint(4));
+        assert!(!test_vm.add_breakpoint(4));
+        test_vm.add_breakpoint(8);
+        assert_eq!(test_vm.run(), Ok(ExitReason::Breakpoint(4)));
+        assert_eq!(test_vm.registers[0], 1);
+        // Running again stays put, resuming steps over the breakpoint
+        assert_eq!(test_vm.run(), Ok(ExitReason::Breakpoint(4)));
+        assert_eq!(test_vm.resume(), Ok(ExitReason::Breakpoint(8)));
+        assert_eq!(test_vm.registers[0], 2);
+        assert!(test_vm.remove_breakpoint(4));
+        assert_eq!(test_vm.breakpoints().collect::<Vec<_>>(), vec![&8]);
+        assert_eq!(test_vm.resume(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.registers[0], 3);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut image = pie::write_header(2);
+        image.extend_from_slice(&[7, 9, 17, 0, 0, 0, 31, 0, 0, 0]);
+        let mut test_vm = VM::new();
+        test_vm.load_program(image).unwrap();
+        test_vm.registers[0] = 6;
+        test_vm.float_registers[1] = 1.5;
+        test_vm.equal_flag = true;
+        // aloc $0 then push $0
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.heap.len(), 8);
+        test_vm.reset();
+        assert_eq!(test_vm.registers, [0; 32]);
+        assert_eq!(test_vm.float_registers, [0.0; 32]);
+        assert!(!test_vm.equal_flag);
+        assert_eq!(test_vm.stack, vec![]);
+        assert_eq!(test_vm.heap, vec![7, 9]);
+        assert_eq!(test_vm.read_only, 2);
+        assert_eq!(test_vm.pc, PIE_HEADER_LENGTH + 2);
+
+        test_vm.program = vec![5, 0, 0, 0];
+        test_vm.reset();
+        assert_eq!((test_vm.pc, test_vm.heap.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_clear_program() {
+        let mut image = pie::write_header(2);
+        image.extend_from_slice(&[7, 9, 17, 0, 0, 0]);
+        let mut test_vm = VM::new();
+        test_vm.load_program(image).unwrap();
+        test_vm.registers[0] = 6;
+        test_vm.run().unwrap();
+        test_vm.add_breakpoint(100);
+        test_vm.clear_program();
+        assert!(test_vm.program.is_empty());
+        assert_eq!(test_vm.registers, [0; 32]);
+        assert_eq!((test_vm.pc, test_vm.heap.len(), test_vm.read_only), (0, 0, 0));
+        assert_eq!(test_vm.breakpoints().collect::<Vec<_>>(), vec![&100]);
+
+        // Typed code gets a fresh heap instead of writing over the old read-only data
+        test_vm.add_bytes(vec![0, 1, 0, 1, 17, 1, 0, 0, 0, 1, 0, 0, 26, 1, 1, 0]);
+        assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(test_vm.heap, vec![0]);
+    }
+
+    #[test]
+    fn test_bad_register_fault() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![0, 0, 0, 1, 1, 0, 40, 2];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::BadRegister(40), pc: 4, opcode: Some(1) });
+    }
+
+    #[test]
+    fn test_operands_checked_before_executing() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![31, 1, 0, 0, 1, 0, 0, 40];
+        test_vm.run_once().unwrap();
+        let fault = test_vm.run_once().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::BadRegister(40));
+        // The fault is raised before the operands are consumed
+        assert_eq!(test_vm.pc, 5);
+    }
+
+    #[test]
+    fn test_truncated_instruction_fault() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![0, 0, 1];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::TruncatedInstruction);
+    }
+
+    #[test]
+    fn test_pc_out_of_bounds_fault() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 100;
+        test_vm.program = vec![6, 0, 0, 0];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::PcOutOfBounds, pc: 100, opcode: None });
+    }
+
+    #[test]
+    fn test_heap_overflow_fault() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -1;
+        test_vm.program = vec![17, 0, 0, 0];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::HeapOverflow);
+    }
+
+
+    #[test]
+    fn test_load_program() {
+        let mut test_vm = VM::new();
+        assert_eq!(test_vm.load_program(vec![5, 0, 0, 0]), Err(HeaderError::TooShort { length: 4 }));
+        test_vm.load_program(pie::prepend_header(vec![0, 0, 0, 7, 5, 0, 0, 0])).unwrap();
+        assert!(test_vm.verify_header());
+        assert_eq!(test_vm.pc, PIE_HEADER_LENGTH);
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.registers[0], 7);
+    }
+
+
+    #[test]
+    fn test_inc_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 7;
+        test_vm.program = vec![18, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.registers[0], 8);
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_dec_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 7;
+        test_vm.program = vec![19, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.registers[0], 6);
+        assert_eq!(test_vm.pc, 4);
+    }
+
+    #[test]
+    fn test_nop_opcode() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![16, 0, 0, 0, 5, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+    }
+
+
+    #[test]
+    fn test_bitwise_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 0b1100;
+        test_vm.registers[1] = 0b1010;
+        test_vm.program = vec![34, 0, 1, 2, 35, 0, 1, 3, 36, 0, 1, 4, 37, 0, 5, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], 0b1000);
+        assert_eq!(test_vm.registers[3], 0b1110);
+        assert_eq!(test_vm.registers[4], 0b0110);
+        assert_eq!(test_vm.registers[5], !0b1100);
+    }
+
+    #[test]
+    fn test_shift_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -16;
+        test_vm.registers[1] = 2;
+        test_vm.registers[2] = 33;
+        test_vm.program = vec![38, 0, 1, 3, 39, 0, 1, 4, 40, 0, 1, 5, 38, 0, 2, 6];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[3], -64);
+        assert_eq!(test_vm.registers[4], 0x3fff_fffc);
+        assert_eq!(test_vm.registers[5], -4);
+        // Only the low 5 bits of the amount count, so 33 shifts by 1
+        assert_eq!(test_vm.registers[6], -32);
+    }
+
+    #[test]
+    fn test_byte_load_store_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.heap = vec![0; 8];
+        test_vm.registers[0] = 0x1ff;
+        test_vm.registers[1] = 2;
+        test_vm.program = vec![26, 0, 1, 3, 24, 2, 1, 3];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.heap[5], 0xff);
+        assert_eq!(test_vm.registers[2], 0xff);
+    }
+
+    #[test]
+    fn test_word_load_store_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.heap = vec![0; 8];
+        test_vm.registers[0] = -2;
+        test_vm.program = vec![27, 0, 1, 4, 25, 2, 1, 4];
+        test_vm.run().unwrap();
+        assert_eq!(&test_vm.heap[4..8], &[0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(test_vm.registers[2], -2);
+    }
+
+    #[test]
+    fn test_heap_out_of_bounds_fault() {
+        let mut test_vm = VM::new();
+        test_vm.heap = vec![0; 8];
+        test_vm.program = vec![25, 2, 1, 5];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::HeapOutOfBounds, pc: 0, opcode: Some(25) });
+    }
+
+
+    #[test]
+    fn test_call_and_ret_opcodes() {
+        let mut test_vm = VM::new();
+        // call 12, hlt, nop, inc $0, ret
+        test_vm.program = vec![29, 0, 12, 0, 5, 0, 0, 0, 16, 0, 0, 0, 18, 0, 0, 0, 30, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 12);
+        assert_eq!(test_vm.call_stack, vec![4]);
+        assert_eq!(test_vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(test_vm.registers[0], 1);
+        assert!(test_vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_push_and_pop_opcodes() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 42;
+        test_vm.program = vec![31, 0, 0, 0, 32, 1, 0, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[1], 42);
+        assert!(test_vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_stack_faults() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![30, 0, 0, 0];
+        assert_eq!(test_vm.run().unwrap_err().kind, FaultKind::StackUnderflow);
+
+        let mut test_vm = VM::new();
+        test_vm.stack_limit = 2;
+        test_vm.program = vec![31, 0, 0, 0, 31, 0, 0, 0, 31, 0, 0, 0];
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::StackOverflow, pc: 8, opcode: Some(31) });
+
+        let mut test_vm = VM::new();
+        test_vm.stack_limit = 3;
+        // a subroutine that calls itself forever
+        test_vm.program = vec![29, 0, 0, 0];
+        assert_eq!(test_vm.run().unwrap_err().kind, FaultKind::StackOverflow);
+        assert_eq!(test_vm.call_stack.len(), 3);
+    }
+
+
+    #[test]
+    fn test_signed_relative_jumps() {
+        let mut test_vm = VM::new();
+        // a negative distance makes JMPF go backward
+        test_vm.registers[0] = -8;
+        test_vm.program = vec![16, 0, 0, 0, 16, 0, 0, 0, 7, 0, 0, 0];
+        test_vm.pc = 8;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+
+        let mut test_vm = VM::new();
+        test_vm.program = vec![33, 0, 4, 0, 33, 0, 12, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 0);
+        test_vm.pc = 4;
+        let fault = test_vm.run_once().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::PcOutOfBounds, pc: 4, opcode: Some(33) });
+    }
+
+    #[test]
+    fn test_jeq_not_taken_skips_padding() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![15, 0, 0, 0, 5, 0, 0, 0];
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 4);
+    }
+
+
+    #[test]
+    fn test_load_sign_extends() {
+        let mut test_vm = VM::new();
+        test_vm.program = vec![0, 0, 0xff, 0xff, 0, 1, 0x80, 0];
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[0], -1);
+        assert_eq!(test_vm.registers[1], -32768);
+    }
+
+
+    #[test]
+    fn test_load_program_data_section() {
+        let mut image = pie::write_header(2);
+        image.extend_from_slice(&[7, 9, 24, 0, 1, 1, 26, 0, 1, 0]);
+        let mut test_vm = VM::new();
+        test_vm.load_program(image).unwrap();
+        assert_eq!(test_vm.pc, PIE_HEADER_LENGTH + 2);
+        assert_eq!(test_vm.heap, vec![7, 9]);
+        // loadb $0 $1 #1 reads the data, storeb $0 $1 #0 may not change it
+        test_vm.registers[1] = 0;
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(test_vm.registers[0], 9);
+        assert_eq!(fault, VmFault { kind: FaultKind::ReadOnlyWrite, pc: PIE_HEADER_LENGTH + 6, opcode: Some(26) });
+    }
+
+    #[test]
+    fn test_aloc_keeps_data_section() {
+        let mut image = pie::write_header(2);
+        image.extend_from_slice(&[7, 9, 17, 0, 0, 0, 17, 1, 0, 0]);
+        let mut test_vm = VM::new();
+        test_vm.load_program(image).unwrap();
+        // aloc $0 grows the heap by 3 and aloc $1 may give those back, but no more
+        test_vm.registers[0] = 3;
+        test_vm.registers[1] = -3;
+        assert_eq!(test_vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(test_vm.heap, vec![7, 9]);
+        test_vm.reset();
+        test_vm.pc += 4;
+        test_vm.registers[1] = -1;
+        let fault = test_vm.run().unwrap_err();
+        assert_eq!(fault, VmFault { kind: FaultKind::ReadOnlyWrite, pc: PIE_HEADER_LENGTH + 6, opcode: Some(17) });
+        assert_eq!(test_vm.heap, vec![7, 9]);
+    }
+
 }
\ No newline at end of file